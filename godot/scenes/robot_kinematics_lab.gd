@@ -26,5 +26,9 @@ func _on_controller_left_button_released(name: String) -> void:
 		
 		object = find_child("Blue2")
 		object.reset = true
+
+		var ik = find_child("RsMannequinIK", true, false)
+		if ik:
+			ik.reset_to_rest()
 		
 		
@@ -0,0 +1,158 @@
+//! Center of mass of the whole skeleton and a balance task keeping its ground projection inside
+//! a support polygon
+//!
+//! The ground is the x-z plane of the skeleton (y pointing up). The mass of each bone is
+//! concentrated in its origin.
+
+use godot::prelude::*;
+
+use crate::geometric::BonePoses;
+
+fn mass(masses: &[f32], bone: usize) -> f32 {
+    masses.get(bone).copied().unwrap_or(1.0)
+}
+
+/// Bones without an entry in `masses` weigh 1 kg
+pub fn center_of_mass(poses: &BonePoses, masses: &[f32]) -> Vector3 {
+    let total = (0..poses.poses.len())
+        .map(|bone| mass(masses, bone))
+        .sum::<f32>();
+    if total <= 0.0 {
+        return Vector3::ZERO;
+    }
+    poses
+        .poses
+        .iter()
+        .enumerate()
+        .map(|(bone, pose)| pose.origin * mass(masses, bone))
+        .fold(Vector3::ZERO, |sum, x| sum + x)
+        / total
+}
+
+/// Jacobian (`3 x active.len()`, column-major) of the center of mass: the mass-weighted average
+/// of the Jacobians of the bone origins
+pub fn center_of_mass_jacobian(poses: &BonePoses, masses: &[f32], active: &[i32]) -> Vec<f32> {
+    let total = (0..poses.poses.len())
+        .map(|bone| mass(masses, bone))
+        .sum::<f32>();
+    let mut jacobian = vec![0f32; 3 * active.len()];
+    if total <= 0.0 {
+        return jacobian;
+    }
+    (0..poses.poses.len()).for_each(|bone| {
+        let weight = mass(masses, bone) / total;
+        poses
+            .point_jacobian(active, bone, Vector3::ZERO)
+            .iter()
+            .zip(jacobian.iter_mut())
+            .for_each(|(x, sum)| *sum += weight * x);
+    });
+    jacobian
+}
+
+/// Ground projection of a point in skeleton coordinates
+pub fn ground_projection(point: Vector3) -> Vector2 {
+    Vector2::new(point.x, point.z)
+}
+
+fn contains(polygon: &[Vector2], point: Vector2) -> bool {
+    // Ray casting (even-odd rule)
+    let mut inside = false;
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Vector from `point` to the closest point of the support polygon if `point` lies outside of it
+///
+/// Returns `None` if the point is inside or the polygon is degenerate (less than three vertices)
+pub fn support_correction(polygon: &[Vector2], point: Vector2) -> Option<Vector2> {
+    if polygon.len() < 3 || contains(polygon, point) {
+        return None;
+    }
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| {
+            let edge = *b - *a;
+            let t = ((point - *a).dot(edge) / edge.length_squared().max(1e-8)).clamp(0.0, 1.0);
+            *a + edge * t - point
+        })
+        .min_by(|x, y| x.length_squared().total_cmp(&y.length_squared()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Vec<Vector2> {
+        vec![
+            Vector2::new(-1.0, -1.0),
+            Vector2::new(1.0, -1.0),
+            Vector2::new(1.0, 1.0),
+            Vector2::new(-1.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn contains_points_inside() {
+        assert!(contains(&square(), Vector2::ZERO));
+        assert!(contains(&square(), Vector2::new(0.9, -0.9)));
+        assert!(!contains(&square(), Vector2::new(1.1, 0.0)));
+        assert!(!contains(&square(), Vector2::new(-2.0, 2.0)));
+
+        // Notch of a concave polygon
+        let concave = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(2.0, 0.0),
+            Vector2::new(2.0, 2.0),
+            Vector2::new(1.0, 1.0),
+            Vector2::new(0.0, 2.0),
+        ];
+        assert!(contains(&concave, Vector2::new(0.5, 0.5)));
+        assert!(!contains(&concave, Vector2::new(1.0, 1.5)));
+    }
+
+    #[test]
+    fn no_correction_inside() {
+        assert_eq!(support_correction(&square(), Vector2::new(0.5, 0.2)), None);
+    }
+
+    #[test]
+    fn corrects_towards_the_closest_edge() {
+        let correction = support_correction(&square(), Vector2::new(3.0, 0.5)).unwrap();
+        assert!(correction.is_equal_approx(Vector2::new(-2.0, 0.0)));
+    }
+
+    #[test]
+    fn corrects_towards_the_closest_corner() {
+        let correction = support_correction(&square(), Vector2::new(2.0, -3.0)).unwrap();
+        assert!(correction.is_equal_approx(Vector2::new(-1.0, 2.0)));
+    }
+
+    #[test]
+    fn ignores_degenerate_polygons() {
+        let segment = [Vector2::ZERO, Vector2::new(1.0, 0.0)];
+        assert_eq!(support_correction(&segment, Vector2::new(5.0, 5.0)), None);
+    }
+
+    #[test]
+    fn center_of_mass_weighs_the_bones() {
+        let poses = BonePoses {
+            poses: vec![
+                Transform3D::IDENTITY,
+                Transform3D::new(Basis::IDENTITY, Vector3::new(4.0, 0.0, 0.0)),
+            ],
+            parents: vec![-1, 0],
+            weights: vec![1.0; 2],
+        };
+        assert!(center_of_mass(&poses, &[]).is_equal_approx(Vector3::new(2.0, 0.0, 0.0)));
+        assert!(center_of_mass(&poses, &[3.0]).is_equal_approx(Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(center_of_mass(&poses, &[0.0, 0.0]), Vector3::ZERO);
+    }
+}
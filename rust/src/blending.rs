@@ -0,0 +1,143 @@
+//! Blending between the live target of an effector and its default target
+//!
+//! Replaces the hard switch to the default target, which makes the effector snap whenever the
+//! live target leaves its reach.
+
+use godot::prelude::*;
+
+#[derive(GodotConvert, Var, Export, Debug, Clone, Copy)]
+#[godot(via = GString)]
+pub enum BlendPolicy {
+    /// Hard switch as soon as the target is out of reach
+    Switch, // first enumerator is default.
+    /// Weight grows with the distance beyond `min_dist` over `falloff` meters
+    Falloff,
+    /// Fades over `fade_time` seconds whenever the target leaves or enters the reach
+    CrossFade,
+    /// Like `CrossFade` but switches at `min_dist + hysteresis` and back at
+    /// `min_dist - hysteresis` only
+    Hysteresis,
+}
+
+impl BlendPolicy {
+    /// Whether [`TargetBlend::update`] depends on `out_of_reach`
+    pub fn uses_reach(&self) -> bool {
+        matches!(self, Self::Switch | Self::CrossFade)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlendSettings {
+    pub policy: BlendPolicy,
+    pub min_dist: f32,
+    pub falloff: f32,
+    pub fade_time: f32,
+    pub hysteresis: f32,
+}
+
+/// Last verdict of the reachability check of one effector, reused between frames
+#[derive(Debug, Clone, Default)]
+pub struct ReachCheck {
+    // target (global coordinates) and whether it was out of reach
+    verdict: Option<(Vector3, bool)>,
+    // seconds since the verdict
+    age: f32,
+}
+
+impl ReachCheck {
+    /// Whether `target` is out of reach. Reuses the last verdict if `target` moved less than
+    /// `tolerance` since or the verdict is younger than `interval` seconds, else runs `check`
+    pub fn out_of_reach(
+        &mut self,
+        target: Vector3,
+        dt: f32,
+        interval: f32,
+        tolerance: f32,
+        check: impl FnOnce() -> bool,
+    ) -> bool {
+        self.age += dt;
+        match self.verdict {
+            Some((checked, verdict))
+                if self.age < interval || checked.distance_to(target) < tolerance =>
+            {
+                verdict
+            }
+            _ => {
+                let verdict = check();
+                self.verdict = Some((target, verdict));
+                self.age = 0.0;
+                verdict
+            }
+        }
+    }
+}
+
+/// Blend state of one effector
+#[derive(Debug, Clone, Default)]
+pub struct TargetBlend {
+    /// Weight of the default target
+    weight: f32,
+    engaged: bool,
+}
+
+impl TargetBlend {
+    /// Weight of the default target after the last update
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Advances the blend by `dt` seconds and returns the weight of the default target
+    ///
+    /// `out_of_reach` is the verdict of the fallback check (only used by the policies for which
+    /// [`BlendPolicy::uses_reach`] holds), `distance` the distance between the effector and its
+    /// live target.
+    pub fn update(
+        &mut self,
+        settings: &BlendSettings,
+        out_of_reach: bool,
+        distance: f32,
+        dt: f32,
+    ) -> f32 {
+        let fade = |weight: f32, engaged: bool| {
+            let goal = if engaged { 1.0 } else { 0.0 };
+            if settings.fade_time <= 0.0 {
+                goal
+            } else {
+                let step = dt / settings.fade_time;
+                weight + (goal - weight).clamp(-step, step)
+            }
+        };
+
+        match settings.policy {
+            BlendPolicy::Switch => {
+                self.engaged = out_of_reach;
+                self.weight = if out_of_reach { 1.0 } else { 0.0 };
+            }
+            BlendPolicy::Falloff => {
+                self.weight = if settings.falloff <= 0.0 {
+                    if distance > settings.min_dist {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                } else {
+                    ((distance - settings.min_dist) / settings.falloff).clamp(0.0, 1.0)
+                };
+                self.engaged = self.weight > 0.0;
+            }
+            BlendPolicy::CrossFade => {
+                self.engaged = out_of_reach;
+                self.weight = fade(self.weight, self.engaged);
+            }
+            BlendPolicy::Hysteresis => {
+                self.engaged = if self.engaged {
+                    distance > settings.min_dist - settings.hysteresis
+                } else {
+                    distance > settings.min_dist + settings.hysteresis
+                };
+                self.weight = fade(self.weight, self.engaged);
+            }
+        }
+        self.weight
+    }
+}
@@ -0,0 +1,45 @@
+//! Ground contacts that lock feet in place while the upper body follows its targets
+
+use godot::{
+    classes::{PhysicsDirectSpaceState3D, PhysicsRayQueryParameters3D},
+    prelude::*,
+};
+
+/// A foot that can be planted on the ground
+#[derive(Debug, Clone)]
+pub struct FootContact {
+    pub name: GString,
+    pub bone: i32,
+    /// Locked position of the foot bone (global coordinates) while the foot is planted: above
+    /// the contact point by the bone's height above the ground
+    pub anchor: Option<Vector3>,
+}
+
+impl FootContact {
+    pub fn new(name: GString, bone: i32) -> Self {
+        Self {
+            name,
+            bone,
+            anchor: None,
+        }
+    }
+}
+
+/// Casts a ray straight down through `position` (global coordinates) and returns the point
+/// where it hits the ground, if any
+///
+/// The ray starts `probe` meters above and ends `probe` meters below `position`.
+pub fn ground_contact(
+    space: &mut Gd<PhysicsDirectSpaceState3D>,
+    position: Vector3,
+    probe: f32,
+) -> Option<Vector3> {
+    let query = PhysicsRayQueryParameters3D::create(
+        position + Vector3::UP * probe,
+        position + Vector3::DOWN * probe,
+    )?;
+    space
+        .intersect_ray(&query)
+        .get("position")
+        .map(|position| position.to::<Vector3>())
+}
@@ -0,0 +1,67 @@
+//! Debug overlay of the inverse kinematics drawn with an [`ImmediateMesh`]
+
+use godot::{
+    classes::{
+        ImmediateMesh, StandardMaterial3D,
+        base_material_3d::{Flags, ShadingMode},
+        mesh::PrimitiveType,
+    },
+    prelude::*,
+};
+
+pub const JOINT_AXIS: Color = Color::from_rgb(1.0, 0.8, 0.0);
+pub const RESIDUAL: Color = Color::from_rgb(1.0, 0.0, 1.0);
+pub const JACOBIAN: Color = Color::from_rgb(0.0, 1.0, 1.0);
+
+/// Line segments (global coordinates) collected during a frame
+#[derive(Default)]
+pub struct DebugLines {
+    lines: Vec<(Vector3, Vector3, Color)>,
+}
+
+impl DebugLines {
+    pub fn line(&mut self, from: Vector3, to: Vector3, color: Color) {
+        self.lines.push((from, to, color));
+    }
+
+    pub fn arrow(&mut self, from: Vector3, direction: Vector3, color: Color) {
+        self.line(from, from + direction, color);
+    }
+
+    /// Axes of a coordinate frame in red (x), green (y) and blue (z)
+    pub fn frame(&mut self, transform: Transform3D, size: f32) {
+        let origin = transform.origin;
+        let basis = transform.basis;
+        self.arrow(origin, basis.col_a() * size, Color::RED);
+        self.arrow(origin, basis.col_b() * size, Color::GREEN);
+        self.arrow(origin, basis.col_c() * size, Color::BLUE);
+    }
+
+    /// Replaces the content of `mesh` with the lines
+    pub fn draw(&self, mesh: &mut Gd<ImmediateMesh>) {
+        mesh.clear_surfaces();
+        if self.lines.is_empty() {
+            return;
+        }
+
+        mesh.surface_begin_ex(PrimitiveType::LINES)
+            .material(&material())
+            .done();
+        self.lines.iter().for_each(|(from, to, color)| {
+            mesh.surface_set_color(*color);
+            mesh.surface_add_vertex(*from);
+            mesh.surface_set_color(*color);
+            mesh.surface_add_vertex(*to);
+        });
+        mesh.surface_end();
+    }
+}
+
+/// Unshaded vertex colors that are always visible
+fn material() -> Gd<StandardMaterial3D> {
+    let mut material = StandardMaterial3D::new_gd();
+    material.set_shading_mode(ShadingMode::UNSHADED);
+    material.set_flag(Flags::ALBEDO_FROM_VERTEX_COLOR, true);
+    material.set_flag(Flags::DISABLE_DEPTH_TEST, true);
+    material
+}
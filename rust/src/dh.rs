@@ -0,0 +1,293 @@
+//! Kinematic chains from Denavit–Hartenberg parameters
+//!
+//! Every row of a table describes one revolute joint. With the `Standard` convention, row `i`
+//! holds `a_i`, `alpha_i`, `d_i` and `theta_i` of the transform `Rz(theta_i) Tz(d_i) Tx(a_i)
+//! Rx(alpha_i)` from frame `i - 1` to frame `i`, and joint `i` rotates about `z_{i-1}`. With the
+//! `Modified` (Craig) convention, row `i` holds `a_{i-1}`, `alpha_{i-1}`, `d_i` and `theta_i` of
+//! `Rx(alpha_{i-1}) Tx(a_{i-1}) Rz(theta_i) Tz(d_i)`, and joint `i` rotates about `z_i`.
+
+use godot::{
+    classes::{Resource, Skeleton3D},
+    prelude::*,
+};
+
+use crate::model::{JointKind, KinematicJoint, KinematicModel};
+
+#[derive(GodotConvert, Var, Export, Debug, Clone, Copy)]
+#[godot(via = GString)]
+pub enum DhConvention {
+    Standard, // first enumerator is default.
+    Modified,
+}
+
+/// Denavit–Hartenberg table of a serial chain. Lengths in meters, angles in radians
+#[derive(GodotClass)]
+#[class(tool, init, base=Resource)]
+pub struct RsDhTable {
+    #[export]
+    convention: DhConvention,
+
+    /// Link lengths (one per joint)
+    #[export]
+    a: PackedFloat32Array,
+
+    /// Link twists
+    #[export]
+    alpha: PackedFloat32Array,
+
+    /// Link offsets
+    #[export]
+    d: PackedFloat32Array,
+
+    /// Joint angles of the zero configuration
+    #[export]
+    theta: PackedFloat32Array,
+
+    /// Lower (x) and upper (y) limit of each joint. Joints without an entry or with an empty
+    /// range are not limited
+    #[export]
+    limits: PackedVector2Array,
+
+    /// Names of the links moved by the joints. Missing names are `link<i>`
+    #[export]
+    names: PackedStringArray,
+
+    base: Base<Resource>,
+}
+
+fn rotation(axis: Vector3, angle: f32) -> Transform3D {
+    Transform3D::new(Basis::from_axis_angle(axis, angle), Vector3::ZERO)
+}
+
+fn translation(offset: Vector3) -> Transform3D {
+    Transform3D::new(Basis::IDENTITY, offset)
+}
+
+impl RsDhTable {
+    /// Serial chain with a `base` link. The standard convention appends a fixed `flange` link
+    /// for the last frame
+    pub fn to_model(&self) -> Result<KinematicModel, String> {
+        let count = self.a.len();
+        if [self.alpha.len(), self.d.len(), self.theta.len()] != [count; 3] {
+            return Err("`a`, `alpha`, `d` and `theta` must have the same length".into());
+        }
+        let rows = (0..count)
+            .map(|i| {
+                [
+                    self.a.as_slice()[i],
+                    self.alpha.as_slice()[i],
+                    self.d.as_slice()[i],
+                    self.theta.as_slice()[i],
+                ]
+            })
+            .collect::<Vec<_>>();
+        let names = self
+            .names
+            .as_slice()
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        Ok(dh_model(
+            self.convention,
+            &rows,
+            self.limits.as_slice(),
+            &names,
+        ))
+    }
+}
+
+/// Transform of one row (`a`, `alpha`, `d`, `theta`) of a table
+fn row_transform(convention: DhConvention, [a, alpha, d, theta]: [f32; 4]) -> Transform3D {
+    match convention {
+        DhConvention::Standard => {
+            rotation(Vector3::BACK, theta)
+                * translation(Vector3::BACK * d)
+                * translation(Vector3::RIGHT * a)
+                * rotation(Vector3::RIGHT, alpha)
+        }
+        DhConvention::Modified => {
+            rotation(Vector3::RIGHT, alpha)
+                * translation(Vector3::RIGHT * a)
+                * rotation(Vector3::BACK, theta)
+                * translation(Vector3::BACK * d)
+        }
+    }
+}
+
+/// Serial chain of the rows (`a`, `alpha`, `d`, `theta`) of a table. See [`RsDhTable::to_model`]
+fn dh_model(
+    convention: DhConvention,
+    rows: &[[f32; 4]],
+    limits: &[Vector2],
+    names: &[String],
+) -> KinematicModel {
+    let count = rows.len();
+    let row = |i: usize| row_transform(convention, rows[i]);
+
+    let mut joints = (0..count)
+        .map(|i| {
+            let origin = match convention {
+                // Joint i rotates frame i - 1
+                DhConvention::Standard if i == 0 => Transform3D::IDENTITY,
+                DhConvention::Standard => row(i - 1),
+                DhConvention::Modified => row(i),
+            };
+            let link = names
+                .get(i)
+                .map_or(format!("link{}", i + 1), |name| name.clone());
+            KinematicJoint {
+                name: format!("joint{}", i + 1),
+                link,
+                parent: i.checked_sub(1),
+                origin,
+                axis: Vector3::BACK,
+                kind: JointKind::Revolute,
+                limits: limits
+                    .get(i)
+                    .filter(|limits| limits.x < limits.y)
+                    .map(|limits| (limits.x, limits.y)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if matches!(convention, DhConvention::Standard) && count > 0 {
+        joints.push(KinematicJoint {
+            name: "flange_joint".into(),
+            link: "flange".into(),
+            parent: Some(count - 1),
+            origin: row(count - 1),
+            axis: Vector3::BACK,
+            kind: JointKind::Fixed,
+            limits: None,
+        });
+    }
+
+    KinematicModel {
+        name: "dh_robot".into(),
+        root: "base".into(),
+        joints,
+    }
+}
+
+#[godot_api]
+impl RsDhTable {
+    /// Skeleton with one bone per joint whose z-axes are the joint axes. Null if the table is
+    /// inconsistent
+    #[func]
+    pub fn build_skeleton(&self) -> Option<Gd<Skeleton3D>> {
+        match self.to_model() {
+            Ok(model) => Some(model.build_skeleton()),
+            Err(error) => {
+                godot_error!("Invalid DH table: {error}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    /// Pose of the last link relative to the base link with the joints turned by `angles`
+    fn tip(model: &KinematicModel, angles: &[f32]) -> Transform3D {
+        let mut poses: Vec<Transform3D> = vec![];
+        model
+            .joints
+            .iter()
+            .zip(model.rests())
+            .enumerate()
+            .for_each(|(idx, (joint, rest))| {
+                let parent = joint
+                    .parent
+                    .map_or(Transform3D::IDENTITY, |parent| poses[parent]);
+                let angle = angles.get(idx).copied().unwrap_or_default();
+                poses.push(parent * rest * rotation(Vector3::BACK, angle));
+            });
+        poses.last().copied().unwrap_or(Transform3D::IDENTITY)
+    }
+
+    fn assert_near(actual: Transform3D, expected: Transform3D) {
+        assert!(
+            actual.origin.is_equal_approx(expected.origin)
+                && actual.basis.is_equal_approx(&expected.basis),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn composes_the_rows() {
+        let rows = [
+            [0.3, PI / 2.0, 0.4, 0.1],
+            [0.5, -0.3, 0.2, -0.7],
+            [0.2, 0.0, 0.1, 0.5],
+        ];
+        let product = |convention| {
+            rows.iter().fold(Transform3D::IDENTITY, |product, row| {
+                product * row_transform(convention, *row)
+            })
+        };
+
+        let standard = dh_model(DhConvention::Standard, &rows, &[], &[]);
+        assert_eq!(standard.joints.len(), 4);
+        assert_near(tip(&standard, &[]), product(DhConvention::Standard));
+
+        // The modified convention ends at the frame of the last joint
+        let modified = dh_model(DhConvention::Modified, &rows, &[], &[]);
+        assert_eq!(modified.joints.len(), 3);
+        assert_near(tip(&modified, &[]), product(DhConvention::Modified));
+    }
+
+    #[test]
+    fn joints_add_to_theta() {
+        let rows = [[0.3, PI / 2.0, 0.4, 0.0], [0.5, -0.3, 0.2, 0.0]];
+        let angles = [0.4, -1.1];
+        let turned = rows
+            .iter()
+            .zip(angles)
+            .map(|([a, alpha, d, theta], angle)| [*a, *alpha, *d, theta + angle])
+            .collect::<Vec<_>>();
+
+        [DhConvention::Standard, DhConvention::Modified]
+            .into_iter()
+            .for_each(|convention| {
+                assert_near(
+                    tip(&dh_model(convention, &rows, &[], &[]), &angles),
+                    tip(&dh_model(convention, &turned, &[], &[]), &[]),
+                );
+            });
+    }
+
+    #[test]
+    fn planar_arm() {
+        let rows = [[1.0, 0.0, 0.0, PI / 2.0], [0.5, 0.0, 0.0, 0.0]];
+        let model = dh_model(DhConvention::Standard, &rows, &[], &[]);
+        assert!(
+            tip(&model, &[])
+                .origin
+                .is_equal_approx(Vector3::new(0.0, 1.5, 0.0))
+        );
+        assert!(
+            tip(&model, &[0.0, -PI / 2.0])
+                .origin
+                .is_equal_approx(Vector3::new(0.5, 1.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn names_and_limits() {
+        let rows = [[1.0, 0.0, 0.0, 0.0]; 2];
+        let model = dh_model(
+            DhConvention::Modified,
+            &rows,
+            &[Vector2::new(-1.0, 1.0), Vector2::ZERO],
+            &["upper".to_string()],
+        );
+        assert_eq!(model.joints[0].link, "upper");
+        assert_eq!(model.joints[1].link, "link2");
+        assert_eq!(model.joints[0].limits, Some((-1.0, 1.0)));
+        assert_eq!(model.joints[1].limits, None);
+    }
+}
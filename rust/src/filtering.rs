@@ -0,0 +1,159 @@
+//! Filters for noisy target poses (e.g., hand tracking)
+//!
+//! Hand-tracked targets jitter and the arm amplifies the jitter. The filters smooth the position
+//! and the orientation of a target before the error is computed.
+
+use std::f32::consts::PI;
+
+use godot::{classes::Resource, prelude::*};
+
+#[derive(GodotConvert, Var, Export, Debug, Clone, Copy)]
+#[godot(via = GString)]
+pub enum FilterKind {
+    None, // first enumerator is default.
+    /// Exponential smoothing with a fixed time constant
+    Exponential,
+    /// Adaptive low-pass filter: smooth at low, responsive at high speeds
+    OneEuro,
+    /// Ignores movements smaller than the dead band
+    DeadBand,
+}
+
+/// Filter settings of a target. Assign to `main_target_filter` or `secondary_target_filter`
+#[derive(GodotClass)]
+#[class(tool, init, base=Resource)]
+pub struct RsTargetFilter {
+    #[export]
+    kind: FilterKind,
+
+    /// Time constant of `Exponential` in seconds
+    #[export]
+    #[init(val = 0.1)]
+    time_constant: f32,
+
+    /// Cutoff frequency of `OneEuro` at rest in Hz
+    #[export]
+    #[init(val = 1.0)]
+    min_cutoff: f32,
+
+    /// Increase of the cutoff frequency of `OneEuro` with the speed
+    #[export]
+    #[init(val = 0.5)]
+    beta: f32,
+
+    /// Cutoff frequency of `OneEuro` for estimating the speed in Hz
+    #[export]
+    #[init(val = 1.0)]
+    derivative_cutoff: f32,
+
+    /// Movements below this distance in meters are ignored by `DeadBand`
+    #[export]
+    #[init(val = 0.005)]
+    dead_band: f32,
+
+    /// Rotations below this angle in radians are ignored by `DeadBand`
+    #[export]
+    #[init(val = 0.02)]
+    dead_band_angle: f32,
+
+    base: Base<Resource>,
+}
+
+impl RsTargetFilter {
+    pub fn settings(&self) -> FilterSettings {
+        FilterSettings {
+            kind: self.kind,
+            time_constant: self.time_constant,
+            min_cutoff: self.min_cutoff,
+            beta: self.beta,
+            derivative_cutoff: self.derivative_cutoff,
+            dead_band: self.dead_band,
+            dead_band_angle: self.dead_band_angle,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterSettings {
+    pub kind: FilterKind,
+    pub time_constant: f32,
+    pub min_cutoff: f32,
+    pub beta: f32,
+    pub derivative_cutoff: f32,
+    pub dead_band: f32,
+    pub dead_band_angle: f32,
+}
+
+/// Smoothing factor of a first-order low-pass filter
+fn alpha(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (2.0 * PI * cutoff.max(1e-3));
+    1.0 / (1.0 + tau / dt)
+}
+
+fn angle_between(a: Quaternion, b: Quaternion) -> f32 {
+    2.0 * a.dot(b).abs().min(1.0).acos()
+}
+
+/// Filter state of one target
+#[derive(Debug, Clone, Default)]
+pub struct FilterState {
+    // last output
+    pose: Option<Transform3D>,
+    // filtered linear (m/s) and angular (rad/s) speeds
+    speed: f32,
+    angular_speed: f32,
+}
+
+impl FilterState {
+    /// Filters a new sample taken `dt` seconds after the previous one
+    pub fn apply(&mut self, settings: &FilterSettings, pose: Transform3D, dt: f32) -> Transform3D {
+        let Some(previous) = self.pose else {
+            self.pose = Some(pose);
+            return pose;
+        };
+        if dt <= 0.0 {
+            return previous;
+        }
+
+        let rotation = pose.basis.get_quaternion();
+        let previous_rotation = previous.basis.get_quaternion();
+        let distance = (pose.origin - previous.origin).length();
+        let angle = angle_between(rotation, previous_rotation);
+
+        let (weight, angular_weight) = match settings.kind {
+            FilterKind::None => (1.0, 1.0),
+            FilterKind::Exponential => {
+                let weight = 1.0 - (-dt / settings.time_constant.max(1e-3)).exp();
+                (weight, weight)
+            }
+            FilterKind::OneEuro => {
+                let derivative = alpha(settings.derivative_cutoff, dt);
+                self.speed += derivative * (distance / dt - self.speed);
+                self.angular_speed += derivative * (angle / dt - self.angular_speed);
+                (
+                    alpha(settings.min_cutoff + settings.beta * self.speed, dt),
+                    alpha(settings.min_cutoff + settings.beta * self.angular_speed, dt),
+                )
+            }
+            FilterKind::DeadBand => (
+                if distance > settings.dead_band {
+                    1.0
+                } else {
+                    0.0
+                },
+                if angle > settings.dead_band_angle {
+                    1.0
+                } else {
+                    0.0
+                },
+            ),
+        };
+
+        let filtered = Transform3D::new(
+            Basis::from_quaternion(previous_rotation.slerp(rotation, angular_weight)),
+            previous.origin.lerp(pose.origin, weight),
+        );
+        self.pose = Some(filtered);
+        filtered
+    }
+}
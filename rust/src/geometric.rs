@@ -0,0 +1,296 @@
+//! Forward kinematics and geometric Jacobians computed from the global poses of the bones
+//!
+//! This is the only kinematics of [`crate::mannequin`]: the per-frame solver, the restarts,
+//! offline optimization, reachability and workspace sampling all evaluate a [`Chain`]. Unlike the
+//! [`mannequin::DifferentiableModel`], which only knows the rest poses and its effectors, a chain
+//! evaluates any point of any bone (tool center points, feet, the center of mass) relative to
+//! any base pose (e.g., an animated pose). Every joint rotates about the local z-axis
+//! (`Vector3::BACK`) of its bone.
+
+use godot::{classes::Skeleton3D, prelude::*};
+
+/// Hierarchy and base poses of the bones, read from the skeleton once such that evaluating many
+/// configurations does not call into the engine
+#[derive(Debug, Clone)]
+pub struct Chain {
+    pub parents: Vec<i32>,
+    /// Local poses the angles are relative to (by bone), e.g., the rest or an animated pose
+    pub base: Vec<Transform3D>,
+    /// Fraction of its angle each joint applies (by bone)
+    pub weights: Vec<f32>,
+}
+
+impl Chain {
+    /// `base` defaults to the rest poses and `weights` to 1 for bones they do not cover
+    pub fn new(skeleton: &Gd<Skeleton3D>, base: &[Transform3D], weights: &[f32]) -> Self {
+        let count = skeleton.get_bone_count();
+        Self {
+            parents: (0..count)
+                .map(|idx| skeleton.get_bone_parent(idx))
+                .collect(),
+            base: (0..count)
+                .map(|idx| {
+                    base.get(idx as usize)
+                        .copied()
+                        .unwrap_or_else(|| skeleton.get_bone_rest(idx))
+                })
+                .collect(),
+            weights: (0..count as usize)
+                .map(|idx| weights.get(idx).copied().unwrap_or(1.0))
+                .collect(),
+        }
+    }
+
+    /// Local pose of a bone rotated by its share of `angle` about its joint axis
+    pub fn local(&self, idx: usize, angle: f32) -> Transform3D {
+        self.base[idx] * Transform3D::IDENTITY.rotated(Vector3::BACK, self.weights[idx] * angle)
+    }
+
+    /// Forward kinematics for the angles of all bones (by index, missing angles are zero)
+    pub fn poses(&self, angles: &[f32]) -> BonePoses {
+        let locals = (0..self.base.len())
+            .map(|idx| self.local(idx, angles.get(idx).copied().unwrap_or(0.0)))
+            .collect::<Vec<_>>();
+
+        let mut poses: Vec<Option<Transform3D>> = vec![None; locals.len()];
+        (0..locals.len()).for_each(|idx| {
+            BonePoses::resolve(idx, &self.parents, &locals, &mut poses);
+        });
+
+        BonePoses {
+            poses: poses.into_iter().map(|pose| pose.unwrap()).collect(),
+            parents: self.parents.clone(),
+            weights: self.weights.clone(),
+        }
+    }
+}
+
+/// Global poses (skeleton coordinates) and hierarchy of the bones of a skeleton
+pub struct BonePoses {
+    pub poses: Vec<Transform3D>,
+    pub parents: Vec<i32>,
+    /// Fraction of its angle each joint applies (by bone). Scales the columns of the Jacobians
+    pub weights: Vec<f32>,
+}
+
+impl BonePoses {
+    pub fn from_skeleton(skeleton: &Gd<Skeleton3D>) -> Self {
+        let count = skeleton.get_bone_count();
+        Self {
+            poses: (0..count)
+                .map(|idx| skeleton.get_bone_global_pose(idx))
+                .collect(),
+            parents: (0..count)
+                .map(|idx| skeleton.get_bone_parent(idx))
+                .collect(),
+            weights: vec![1.0; count as usize],
+        }
+    }
+
+    // Parents are not guaranteed to precede their children
+    fn resolve(
+        idx: usize,
+        parents: &[i32],
+        locals: &[Transform3D],
+        poses: &mut [Option<Transform3D>],
+    ) -> Transform3D {
+        if let Some(pose) = poses[idx] {
+            return pose;
+        }
+        let pose = match usize::try_from(parents[idx]) {
+            Ok(parent) => Self::resolve(parent, parents, locals, poses) * locals[idx],
+            Err(_) => locals[idx],
+        };
+        poses[idx] = Some(pose);
+        pose
+    }
+
+    /// Whether `joint` is `bone` or one of its ancestors
+    pub fn moves(&self, joint: usize, bone: usize) -> bool {
+        let mut current = bone as i32;
+        while current >= 0 {
+            if current as usize == joint {
+                return true;
+            }
+            current = self.parents[current as usize];
+        }
+        false
+    }
+
+    /// Position of a point given in the local coordinates of `bone`
+    pub fn point(&self, bone: usize, offset: Vector3) -> Vector3 {
+        self.poses[bone] * offset
+    }
+
+    /// Rotation axis of a joint scaled by its weight, that is, the angular velocity per unit
+    /// change of its angle
+    pub fn axis(&self, joint: usize) -> Vector3 {
+        (self.poses[joint].basis * Vector3::BACK).normalized() * self.weights[joint]
+    }
+
+    /// Angular Jacobian (`3 x active.len()`, column-major) of `bone`: the angular velocity of the
+    /// bone per unit change of each angle
+    pub fn rotation_jacobian(&self, active: &[i32], bone: usize) -> Vec<f32> {
+        active
+            .iter()
+            .flat_map(|joint| {
+                let joint = *joint as usize;
+                if self.moves(joint, bone) {
+                    self.axis(joint).to_array()
+                } else {
+                    [0.0; 3]
+                }
+            })
+            .collect()
+    }
+
+    /// Position Jacobian (`3 x active.len()`, column-major) of a point given in the local
+    /// coordinates of `bone`
+    pub fn point_jacobian(&self, active: &[i32], bone: usize, offset: Vector3) -> Vec<f32> {
+        let point = self.point(bone, offset);
+        active
+            .iter()
+            .flat_map(|joint| {
+                let joint = *joint as usize;
+                if self.moves(joint, bone) {
+                    self.axis(joint)
+                        .cross(point - self.poses[joint].origin)
+                        .to_array()
+                } else {
+                    [0.0; 3]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Rotation vector (axis times angle) of a rotation, taking the shorter way
+pub fn rotation_vector(basis: Basis) -> Vector3 {
+    let mut rotation = basis.orthonormalized().get_quaternion();
+    if rotation.w < 0.0 {
+        rotation = -rotation;
+    }
+    // The arc tangent is accurate for small angles, unlike the arc cosine of `w`
+    let imaginary = Vector3::new(rotation.x, rotation.y, rotation.z);
+    let sine = imaginary.length();
+    if sine > 1e-12 {
+        imaginary / sine * 2.0 * sine.atan2(rotation.w)
+    } else {
+        Vector3::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1e-3;
+
+    fn rotation(axis: Vector3, angle: f32) -> Transform3D {
+        Transform3D::new(
+            Basis::from_axis_angle(axis.normalized(), angle),
+            Vector3::ZERO,
+        )
+    }
+
+    fn translation(offset: Vector3) -> Transform3D {
+        Transform3D::new(Basis::IDENTITY, offset)
+    }
+
+    /// Root with a branch of two bones (listed child first) and a sibling branch of one bone
+    fn chain() -> Chain {
+        Chain {
+            parents: vec![-1, 2, 0, 0],
+            base: vec![
+                translation(Vector3::new(0.1, 0.2, 0.0)) * rotation(Vector3::RIGHT, 0.3),
+                translation(Vector3::new(0.0, 0.6, 0.1))
+                    * rotation(Vector3::new(1.0, 1.0, 0.0), 0.7),
+                translation(Vector3::new(0.0, 0.4, 0.0)) * rotation(Vector3::UP, -0.5),
+                translation(Vector3::new(0.3, 0.0, 0.0)),
+            ],
+            weights: vec![1.0, 0.5, 1.0, 1.0],
+        }
+    }
+
+    const ANGLES: [f32; 4] = [0.2, -0.4, 0.9, 0.1];
+    const ACTIVE: [i32; 3] = [0, 2, 1];
+
+    /// Central differences of `f` for each active angle (`3 x ACTIVE.len()`, column-major)
+    fn differences(difference: impl Fn(&BonePoses, &BonePoses) -> Vector3) -> Vec<f32> {
+        let chain = chain();
+        ACTIVE
+            .iter()
+            .flat_map(|joint| {
+                let mut plus = ANGLES;
+                let mut minus = ANGLES;
+                plus[*joint as usize] += STEP;
+                minus[*joint as usize] -= STEP;
+                (difference(&chain.poses(&plus), &chain.poses(&minus)) / (2.0 * STEP)).to_array()
+            })
+            .collect()
+    }
+
+    fn assert_near(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(actual, expected)| (actual - expected).abs() < 1e-3),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn resolves_parents_after_children() {
+        let poses = chain().poses(&[]);
+        let chain = chain();
+        assert_eq!(poses.poses[2], chain.base[0] * chain.base[2]);
+        assert_eq!(
+            poses.poses[1],
+            chain.base[0] * chain.base[2] * chain.base[1]
+        );
+        assert!(poses.moves(2, 1) && poses.moves(0, 3));
+        assert!(!poses.moves(1, 2) && !poses.moves(2, 3));
+    }
+
+    #[test]
+    fn point_jacobian_matches_differences() {
+        let poses = chain().poses(&ANGLES);
+        [
+            (1, Vector3::new(0.1, 0.2, 0.3)),
+            (3, Vector3::new(0.0, 0.5, 0.0)),
+        ]
+        .into_iter()
+        .for_each(|(bone, offset)| {
+            assert_near(
+                &poses.point_jacobian(&ACTIVE, bone, offset),
+                &differences(|plus, minus| plus.point(bone, offset) - minus.point(bone, offset)),
+            );
+        });
+    }
+
+    #[test]
+    fn rotation_jacobian_matches_differences() {
+        let poses = chain().poses(&ANGLES);
+        [1, 2, 3].into_iter().for_each(|bone| {
+            assert_near(
+                &poses.rotation_jacobian(&ACTIVE, bone),
+                &differences(|plus, minus| {
+                    rotation_vector(plus.poses[bone].basis * minus.poses[bone].basis.inverse())
+                }),
+            );
+        });
+    }
+
+    #[test]
+    fn rotation_vector_takes_the_shorter_way() {
+        assert_eq!(rotation_vector(Basis::IDENTITY), Vector3::ZERO);
+        let axis = Vector3::new(1.0, -2.0, 0.5).normalized();
+        assert!(rotation_vector(Basis::from_axis_angle(axis, 0.8)).is_equal_approx(axis * 0.8));
+        assert!(
+            rotation_vector(Basis::from_axis_angle(axis, 1.5 * std::f32::consts::PI))
+                .is_equal_approx(-axis * 0.5 * std::f32::consts::PI)
+        );
+    }
+}
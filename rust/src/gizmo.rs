@@ -0,0 +1,292 @@
+//! Editor gizmo showing the rotation axis and the limits of each active joint
+//!
+//! The limits can be changed by dragging the handles at the ends of the arcs.
+
+use std::f32::consts::PI;
+
+use godot::{
+    classes::{
+        Camera3D, EditorInterface, EditorNode3DGizmo, EditorNode3DGizmoPlugin, EditorPlugin,
+        IEditorNode3DGizmoPlugin, IEditorPlugin, SkeletonModifier3D,
+    },
+    prelude::*,
+};
+
+use crate::mannequin::RsMannequinIK;
+
+const AXIS_LENGTH: f32 = 0.15; // meters
+const ARC_RADIUS: f32 = 0.1; // meters
+const ARC_SEGMENTS: usize = 32;
+// Equal limits mean unlimited
+const MIN_RANGE: f32 = PI / 180.0; // radians
+
+/// Registers the gizmo with the editor
+#[derive(GodotClass)]
+#[class(tool, init, base=EditorPlugin, editor_plugin)]
+pub struct RsMannequinEditorPlugin {
+    base: Base<EditorPlugin>,
+    gizmo: Option<Gd<RsJointGizmoPlugin>>,
+}
+
+#[godot_api]
+impl IEditorPlugin for RsMannequinEditorPlugin {
+    fn enter_tree(&mut self) {
+        let mut gizmo = RsJointGizmoPlugin::new_gd();
+        gizmo.bind_mut().create_materials();
+        self.base_mut().add_node_3d_gizmo_plugin(&gizmo);
+        self.gizmo = Some(gizmo);
+    }
+
+    fn exit_tree(&mut self) {
+        if let Some(gizmo) = self.gizmo.take() {
+            self.base_mut().remove_node_3d_gizmo_plugin(&gizmo);
+        }
+    }
+}
+
+#[derive(GodotClass)]
+#[class(tool, init, base=EditorNode3DGizmoPlugin)]
+pub struct RsJointGizmoPlugin {
+    base: Base<EditorNode3DGizmoPlugin>,
+}
+
+impl RsJointGizmoPlugin {
+    fn create_materials(&mut self) {
+        self.base_mut()
+            .create_material("axis", Color::from_rgb(1.0, 0.8, 0.0));
+        self.base_mut()
+            .create_material("limit", Color::from_rgb(0.0, 1.0, 1.0));
+        self.base_mut().create_handle_material("handles");
+    }
+
+    /// Zero-angle frames of the active joints in the coordinates of the modifier
+    fn joint_frames(ik: &Gd<RsMannequinIK>) -> Option<Vec<(usize, Transform3D)>> {
+        let node = ik.clone().upcast::<SkeletonModifier3D>();
+        let skeleton = node.get_skeleton()?;
+        let to_node =
+            node.get_global_transform().affine_inverse() * skeleton.get_global_transform();
+
+        Some(
+            ik.bind()
+                .active_bones()
+                .iter()
+                .map(|idx| {
+                    let parent = skeleton.get_bone_parent(*idx);
+                    let parent_pose = if parent == -1 {
+                        Transform3D::IDENTITY
+                    } else {
+                        skeleton.get_bone_global_pose(parent)
+                    };
+                    (
+                        *idx as usize,
+                        to_node * parent_pose * skeleton.get_bone_rest(*idx),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn modifier(gizmo: &Gd<EditorNode3DGizmo>) -> Option<Gd<RsMannequinIK>> {
+        gizmo.get_node_3d()?.try_cast::<RsMannequinIK>().ok()
+    }
+
+    fn arc_point(frame: Transform3D, angle: f32) -> Vector3 {
+        frame * (Basis::from_axis_angle(Vector3::BACK, angle) * Vector3::RIGHT * ARC_RADIUS)
+    }
+}
+
+#[godot_api]
+impl IEditorNode3DGizmoPlugin for RsJointGizmoPlugin {
+    fn get_gizmo_name(&self) -> GString {
+        "RsMannequinIK joints".into()
+    }
+
+    fn has_gizmo(&self, for_node_3d: Option<Gd<Node3D>>) -> bool {
+        for_node_3d.is_some_and(|node| node.try_cast::<RsMannequinIK>().is_ok())
+    }
+
+    fn redraw(&mut self, gizmo: Option<Gd<EditorNode3DGizmo>>) {
+        let Some(mut gizmo) = gizmo else {
+            return;
+        };
+        gizmo.clear();
+
+        let Some(ik) = Self::modifier(&gizmo) else {
+            return;
+        };
+        let Some(frames) = Self::joint_frames(&ik) else {
+            return;
+        };
+
+        let mut axes = PackedVector3Array::new();
+        let mut arcs = PackedVector3Array::new();
+        let mut handles = PackedVector3Array::new();
+        let mut ids = PackedInt32Array::new();
+
+        frames.iter().enumerate().for_each(|(joint, (idx, frame))| {
+            let (lower, upper) = ik.bind().limits(*idx).unwrap_or((-PI, PI));
+
+            axes.push(frame.origin);
+            axes.push(frame.origin + frame.basis.col_c().normalized() * AXIS_LENGTH);
+
+            // Arc with spokes at both ends
+            arcs.push(frame.origin);
+            arcs.push(Self::arc_point(*frame, lower));
+            (0..ARC_SEGMENTS).for_each(|segment| {
+                let step = (upper - lower) / ARC_SEGMENTS as f32;
+                arcs.push(Self::arc_point(*frame, lower + step * segment as f32));
+                arcs.push(Self::arc_point(*frame, lower + step * (segment + 1) as f32));
+            });
+            arcs.push(frame.origin);
+            arcs.push(Self::arc_point(*frame, upper));
+
+            handles.push(Self::arc_point(*frame, lower));
+            handles.push(Self::arc_point(*frame, upper));
+            ids.push(2 * joint as i32);
+            ids.push(2 * joint as i32 + 1);
+        });
+
+        if let Some(material) = self.base_mut().get_material("axis") {
+            gizmo.add_lines(&axes, &material);
+        }
+        if let Some(material) = self.base_mut().get_material("limit") {
+            gizmo.add_lines(&arcs, &material);
+        }
+        if let Some(material) = self.base_mut().get_material("handles") {
+            gizmo.add_handles(&handles, &material, &ids);
+        }
+    }
+
+    fn get_handle_name(
+        &self,
+        gizmo: Option<Gd<EditorNode3DGizmo>>,
+        handle_id: i32,
+        _secondary: bool,
+    ) -> GString {
+        let bound = if handle_id % 2 == 0 { "Lower" } else { "Upper" };
+        let bone = gizmo
+            .and_then(|gizmo| Self::modifier(&gizmo))
+            .and_then(|ik| {
+                let idx = *ik.bind().active_bones().get(handle_id as usize / 2)?;
+                let skeleton = ik.upcast::<SkeletonModifier3D>().get_skeleton()?;
+                Some(skeleton.get_bone_name(idx))
+            })
+            .unwrap_or_default();
+        format!("{bound} limit of `{bone}`").into()
+    }
+
+    fn get_handle_value(
+        &self,
+        gizmo: Option<Gd<EditorNode3DGizmo>>,
+        handle_id: i32,
+        _secondary: bool,
+    ) -> Variant {
+        let Some(ik) = gizmo.and_then(|gizmo| Self::modifier(&gizmo)) else {
+            return Variant::nil();
+        };
+        let ik = ik.bind();
+        let Some(idx) = ik.active_bones().get(handle_id as usize / 2) else {
+            return Variant::nil();
+        };
+        let (lower, upper) = ik.limits(*idx as usize).unwrap_or((-PI, PI));
+        if handle_id % 2 == 0 {
+            lower.to_variant()
+        } else {
+            upper.to_variant()
+        }
+    }
+
+    fn set_handle(
+        &mut self,
+        gizmo: Option<Gd<EditorNode3DGizmo>>,
+        handle_id: i32,
+        _secondary: bool,
+        camera: Option<Gd<Camera3D>>,
+        screen_pos: Vector2,
+    ) {
+        let (Some(gizmo), Some(camera)) = (gizmo, camera) else {
+            return;
+        };
+        let Some(mut ik) = Self::modifier(&gizmo) else {
+            return;
+        };
+        let Some(frames) = Self::joint_frames(&ik) else {
+            return;
+        };
+        let Some((idx, frame)) = frames.get(handle_id as usize / 2).copied() else {
+            return;
+        };
+
+        // Intersect the ray through the mouse with the plane of the arc
+        let frame = ik.clone().upcast::<Node3D>().get_global_transform() * frame;
+        let axis = frame.basis.col_c().normalized();
+        let origin = camera.project_ray_origin(screen_pos);
+        let direction = camera.project_ray_normal(screen_pos);
+        let denominator = axis.dot(direction);
+        if denominator.abs() < 1e-6 {
+            return;
+        }
+        let point = origin + direction * (axis.dot(frame.origin - origin) / denominator);
+        let local = frame.affine_inverse() * point;
+        let angle = local.y.atan2(local.x);
+
+        let (lower, upper) = ik.bind().limits(idx).unwrap_or((-PI, PI));
+        if handle_id % 2 == 0 {
+            ik.bind_mut()
+                .set_limits(idx, angle.min(upper - MIN_RANGE), upper);
+        } else {
+            ik.bind_mut()
+                .set_limits(idx, lower, angle.max(lower + MIN_RANGE));
+        }
+        ik.upcast::<Node3D>().update_gizmos();
+    }
+
+    fn commit_handle(
+        &mut self,
+        gizmo: Option<Gd<EditorNode3DGizmo>>,
+        handle_id: i32,
+        _secondary: bool,
+        restore: Variant,
+        cancel: bool,
+    ) {
+        let Some(mut ik) = gizmo.and_then(|gizmo| Self::modifier(&gizmo)) else {
+            return;
+        };
+        let Ok(restore) = restore.try_to::<f32>() else {
+            return;
+        };
+        let Some(idx) = ik
+            .bind()
+            .active_bones()
+            .get(handle_id as usize / 2)
+            .copied()
+        else {
+            return;
+        };
+
+        let idx = idx as usize;
+        let limits = ik.bind().joint_limits();
+        let (lower, upper) = ik.bind().limits(idx).unwrap_or((-PI, PI));
+        if handle_id % 2 == 0 {
+            ik.bind_mut().set_limits(idx, restore, upper);
+        } else {
+            ik.bind_mut().set_limits(idx, lower, restore);
+        }
+        if cancel {
+            ik.upcast::<Node3D>().update_gizmos();
+            return;
+        }
+
+        // The limits before the drag are restored above, the action applies the new ones
+        let previous = ik.bind().joint_limits();
+        let Some(mut undo_redo) = EditorInterface::singleton().get_editor_undo_redo() else {
+            return;
+        };
+        undo_redo.create_action("Change joint limit");
+        undo_redo.add_do_property(&ik, "joint_limits", &limits.to_variant());
+        undo_redo.add_do_method(&ik, "update_gizmos", &[]);
+        undo_redo.add_undo_property(&ik, "joint_limits", &previous.to_variant());
+        undo_redo.add_undo_method(&ik, "update_gizmos", &[]);
+        undo_redo.commit_action();
+    }
+}
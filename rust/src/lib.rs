@@ -1,7 +1,24 @@
 use godot::prelude::*;
 
+pub mod balance;
+pub mod blending;
+pub mod contact;
+pub mod debug;
+pub mod dh;
+pub mod filtering;
+pub mod geometric;
+pub mod gizmo;
+pub mod logging;
 pub mod mannequin;
+pub mod mjcf;
+pub mod model;
+pub mod optimization;
+pub mod prediction;
+pub mod recording;
 pub mod secondary;
+pub mod urdf;
+pub mod workspace;
+pub mod xml;
 
 struct MyExtension;
 
@@ -0,0 +1,111 @@
+//! Logging with verbosity levels, per-category filters and rate limiting
+//!
+//! Per-frame output flooding the console costs a lot of frame time on standalone headsets.
+//! Use [`ik_log`] instead of printing directly.
+
+use std::cell::Cell;
+
+use godot::{
+    classes::{Engine, Time},
+    prelude::*,
+};
+
+#[derive(GodotConvert, Var, Export, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[godot(via = GString)]
+pub enum Verbosity {
+    Error, // first enumerator is default.
+    Warning,
+    Info,
+    Debug,
+}
+
+/// Bit flags matching the `log_categories` property of `RsMannequinIK`
+#[derive(Debug, Clone, Copy)]
+pub enum Category {
+    /// Building the kinematic tree, not rate limited
+    Setup = 1,
+    /// Targets and fallbacks
+    Targets = 2,
+    /// Residuals, Jacobians and updates
+    Solver = 4,
+    /// Poses and orientations
+    Pose = 8,
+}
+
+impl Category {
+    fn index(self) -> usize {
+        (self as u32).trailing_zeros() as usize
+    }
+}
+
+/// Lets each category log during a single frame per interval. Messages within that frame all
+/// pass such that related output stays together
+#[derive(Default)]
+pub struct RateLimiter {
+    // Frame and time in milliseconds when the current window of each category started
+    windows: [Cell<Option<(u64, u64)>>; 4],
+}
+
+impl RateLimiter {
+    pub fn allow(&self, category: Category, interval: f32) -> bool {
+        if matches!(category, Category::Setup) {
+            return true;
+        }
+        let frame = Engine::singleton().get_process_frames();
+        let now = Time::singleton().get_ticks_msec();
+        let window = &self.windows[category.index()];
+
+        match window.get() {
+            Some((start, _)) if start == frame => true,
+            Some((_, time)) if (now - time) as f32 / 1000.0 < interval => false,
+            _ => {
+                window.set(Some((frame, now)));
+                true
+            }
+        }
+    }
+}
+
+/// Whether a message passes the verbosity and the category filter
+pub fn enabled(
+    verbosity: Verbosity,
+    categories: u32,
+    category: Category,
+    level: Verbosity,
+) -> bool {
+    level <= verbosity && categories & category as u32 != 0
+}
+
+/// Logs if `RsMannequinIK::log_enabled` allows it
+///
+/// ```ignore
+/// ik_log!(self, Solver, Debug, "update: {update:?}");
+/// ```
+macro_rules! ik_log {
+    ($ik:expr, $category:ident, Error, $($arg:tt)*) => {
+        if $ik.log_enabled(
+            $crate::logging::Category::$category,
+            $crate::logging::Verbosity::Error,
+        ) {
+            ::godot::global::godot_error!($($arg)*);
+        }
+    };
+    ($ik:expr, $category:ident, Warning, $($arg:tt)*) => {
+        if $ik.log_enabled(
+            $crate::logging::Category::$category,
+            $crate::logging::Verbosity::Warning,
+        ) {
+            ::godot::global::godot_warn!($($arg)*);
+        }
+    };
+    ($ik:expr, $category:ident, $level:ident, $($arg:tt)*) => {
+        if $ik.log_enabled(
+            $crate::logging::Category::$category,
+            $crate::logging::Verbosity::$level,
+        ) {
+            ::godot::global::godot_print!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use ik_log;
@@ -1,21 +1,53 @@
-use crate::secondary::solve_secondary_goals;
+use crate::balance::{
+    center_of_mass, center_of_mass_jacobian, ground_projection, support_correction,
+};
+use crate::blending::{BlendPolicy, BlendSettings, ReachCheck, TargetBlend};
+use crate::contact::{FootContact, ground_contact};
+use crate::debug::{DebugLines, JACOBIAN, JOINT_AXIS, RESIDUAL};
+use crate::dh::RsDhTable;
+use crate::filtering::{FilterState, RsTargetFilter};
+use crate::geometric::{BonePoses, Chain, rotation_vector};
+use crate::logging::{Category, RateLimiter, Verbosity, enabled, ik_log};
+use crate::mjcf::load_mjcf;
+use crate::model::KinematicModel;
+use crate::optimization::{
+    Evaluation, OptimizationOptions, OptimizationResult, Optimizer, optimize,
+};
+use crate::prediction::{Motion, MotionHistory, add_feed_forward};
+use crate::recording::{Frame, Recording};
+use crate::secondary::{
+    add_null_space_task, prioritize, relative_jacobian, solve_relative_constraint,
+    solve_secondary_goals,
+};
+use crate::urdf::{load_urdf, to_urdf};
+use crate::workspace::{Sample, manipulability, reach_envelope_mesh};
 use faer::MatRef;
+use godot::global::randf_range;
 use godot::prelude::*;
 use godot::{
     classes::{
-        BoneAttachment3D, ISkeletonModifier3D, RigidBody3D, Skeleton3D, SkeletonModifier3D,
+        ArrayMesh, BoneAttachment3D, Engine, FileAccess, ISkeletonModifier3D, ImmediateMesh,
+        MeshInstance3D, Skeleton3D, SkeletonModifier3D, Time, file_access::ModeFlags,
         notify::Node3DNotification,
     },
     global::PropertyHint,
     meta::PropertyInfo,
 };
 use itertools::Itertools;
-use mannequin::{
-    Differentiable, DifferentiableModel, NodeLike, arena::iterables::OptimizedDirectionIterable,
-    differentiable::Filterable, faer::solve_linear, godot::GodotTree,
-};
+use mannequin::faer::solve_linear;
 use std::f32::consts::PI;
 
+/// Restart from seed configurations in progress
+#[derive(Debug, Clone, Default)]
+struct Restart {
+    // seeds not optimized yet
+    seeds: Vec<Vec<f32>>,
+    // number of seeds in total
+    count: usize,
+    // best angles so far and their residual
+    best: Option<(Vec<f32>, f32)>,
+}
+
 #[derive(GodotConvert, Var, Export, Debug)]
 #[godot(via = GString)]
 pub enum Method {
@@ -23,164 +55,1880 @@ pub enum Method {
     Solve,
     Secondary,
     Orientation,
+    Grasp,
 }
 
-#[allow(dead_code)]
-#[derive(GodotClass)]
-#[class(tool, base=SkeletonModifier3D)]
-struct RsMannequinIK {
-    #[export]
-    velocity: f32,
+#[allow(dead_code)]
+#[derive(GodotClass)]
+#[class(tool, base=SkeletonModifier3D)]
+pub struct RsMannequinIK {
+    #[export]
+    velocity: f32,
+
+    angles: Vec<f32>,
+
+    #[export]
+    #[var(get,set = set_main_effector)]
+    main_effector: GString,
+
+    #[export]
+    #[var(get,set = set_secondary_effector)]
+    secondary_effector: GString,
+
+    /// Takes precedence over `main_effector`. The bone of the attachment is the effector
+    #[export]
+    #[var(get, set = set_main_effector_attachment)]
+    main_effector_attachment: Option<Gd<BoneAttachment3D>>,
+
+    /// Takes precedence over `secondary_effector`
+    #[export]
+    #[var(get, set = set_secondary_effector_attachment)]
+    secondary_effector_attachment: Option<Gd<BoneAttachment3D>>,
+
+    /// Tool center point (e.g., a gripper tip) relative to the main effector bone. Its origin
+    /// instead of the bone's is moved to the target
+    #[export]
+    main_tcp: Transform3D,
+
+    /// Tool center point relative to the secondary effector bone
+    #[export]
+    secondary_tcp: Transform3D,
+
+    /// Any node (e.g., a pickable object, a marker or an XR controller)
+    #[export]
+    #[var(get, set = set_main_target)]
+    main_target: Option<Gd<Node3D>>,
+
+    #[export]
+    #[var(get, set = set_default_main_target)]
+    default_main_target: Option<Gd<Node3D>>,
+
+    #[export]
+    #[var(get, set = set_secondary_target)]
+    secondary_target: Option<Gd<Node3D>>,
+
+    #[export]
+    #[var(get, set = set_default_secondary_target)]
+    default_secondary_target: Option<Gd<Node3D>>,
+
+    /// Smooths the pose of the main target before computing the error
+    #[export]
+    main_target_filter: Option<Gd<RsTargetFilter>>,
+
+    /// Smooths the pose of the secondary target before computing the error
+    #[export]
+    secondary_target_filter: Option<Gd<RsTargetFilter>>,
+
+    /// Extrapolate moving targets by `latency`
+    #[export]
+    predict_motion: bool,
+
+    /// Time in seconds by which the targets are extrapolated
+    #[export]
+    latency: f32,
+
+    /// Time span in seconds over which the velocity of a target is estimated
+    #[export]
+    prediction_window: f32,
+
+    /// Gain of the velocity feed-forward: the joints additionally move by `J⁺ v dt` such that
+    /// the effectors keep up with their targets' velocities `v`. Fades out while an effector
+    /// falls back to its default target and only applies to the targets the method tracks.
+    /// Requires `predict_motion`
+    #[export]
+    feed_forward: f32,
+
+    /// Position of the secondary relative to the main effector (coordinates of the main tool
+    /// center point) kept by `Method::Grasp`
+    #[export]
+    grasp_offset: Vector3,
+
+    /// Orientation of the secondary relative to the main effector (coordinates of the main tool
+    /// center point) kept by `Method::Grasp`
+    #[export]
+    grasp_rotation: Basis,
+
+    // #[export]
+    // effectors: Array<Gd<Marker3D>>,
+    #[export]
+    #[var(get,set = set_method)]
+    method: Method,
+
+    #[export]
+    min_dist: f32,
+
+    /// How to blend between a target and its default target
+    #[export]
+    blend_policy: BlendPolicy,
+
+    /// Distance in meters beyond `min_dist` over which `Falloff` blends to the default target
+    #[export]
+    blend_falloff: f32,
+
+    /// Duration of a blend in seconds (`CrossFade` and `Hysteresis`)
+    #[export]
+    blend_fade_time: f32,
+
+    /// Half width in meters of the band around `min_dist` in which `Hysteresis` keeps its
+    /// current target
+    #[export]
+    blend_hysteresis: f32,
+
+    /// Lower (x) and upper (y) limit of each bone (by index) in radians. Bones without an entry
+    /// or with an empty range are not limited
+    #[export]
+    joint_limits: PackedVector2Array,
+
+    /// Fall back to the default targets when a target is not reachable (runs the solver to
+    /// convergence) instead of when it is further away than `min_dist`
+    #[export]
+    reachability_fallback: bool,
+
+    /// Seconds between two reachability checks of a moving target. The last verdict is reused in
+    /// between and as long as the target stays within `reach_tolerance` of the checked position
+    #[export]
+    reach_check_interval: f32,
+
+    /// Maximal distance in meters to a target that still counts as reached
+    #[export]
+    reach_tolerance: f32,
+
+    /// Keep the ground projection of the center of mass inside `support_polygon`
+    #[export]
+    balance: bool,
+
+    /// Mass of each bone (by index) in kg. Missing entries weigh 1 kg
+    #[export]
+    bone_masses: PackedFloat32Array,
+
+    /// Support polygon on the ground (x-z plane in skeleton coordinates)
+    #[export]
+    support_polygon: PackedVector2Array,
+
+    /// Bones that are locked to the ground while planted
+    #[export]
+    feet: PackedStringArray,
+
+    /// Plant all feet when the skeleton is set up. The ground is probed in the first physics
+    /// frame after that (not in the editor)
+    #[export]
+    plant_feet: bool,
+
+    /// Distance above and below a foot searched for the ground in meters
+    #[export]
+    ground_probe: f32,
+
+    /// Restart from several seed configurations when the solver gets stuck. One seed is
+    /// optimized per frame
+    #[export]
+    multi_seed: bool,
+
+    /// Number of random seed configurations (in addition to `seed_poses`)
+    #[export]
+    seed_count: i32,
+
+    /// Frames without progress after which the solver counts as stuck
+    #[export]
+    stall_frames: i32,
+
+    /// Precomputed seed configurations (angles for all bones)
+    #[export]
+    seed_poses: Array<PackedFloat32Array>,
+
+    /// Named joint configurations (`PackedFloat32Array` with angles for all bones) for `go_home`
+    #[export]
+    home_poses: Dictionary,
+
+    /// Draw joint axes, effector and target frames, residuals and the directions of the
+    /// Jacobian's columns
+    #[export]
+    debug_draw: bool,
+
+    /// Length of the axes in the debug overlay in meters
+    #[export]
+    debug_size: f32,
+
+    /// Most detailed level of messages that are logged
+    #[export]
+    verbosity: Verbosity,
+
+    /// Categories of messages that are logged
+    #[export(flags = (Setup = 1, Targets = 2, Solver = 4, Pose = 8))]
+    log_categories: u32,
+
+    /// Minimal time in seconds between frames in which a category logs (except for `Setup`)
+    #[export]
+    log_interval: f32,
+
+    /// Used by `optimize_pose` and for restarts from seeds
+    #[export]
+    optimizer: Optimizer,
+
+    /// Rotate the joints relative to the incoming (e.g., animated) pose instead of the rest pose,
+    /// layering the IK on top of an `AnimationPlayer`. The modifier's `influence` is applied by
+    /// the skeleton in either case
+    #[export]
+    layer_on_animation: bool,
+
+    /// File written by `stop_recording` (`.csv` for CSV, else JSON)
+    #[export]
+    recording_path: GString,
+
+    /// Drive the skeleton from the recording loaded by `start_playback` instead of solving
+    #[export]
+    playback: bool,
+
+    /// Denavit–Hartenberg table for `build_from_dh_table`
+    #[export]
+    dh_table: Option<Gd<RsDhTable>>,
+
+    /// Weight of each bone (by index) in the IK. A bone rotates by its weight times its angle
+    /// on top of the incoming (or rest) pose, so bones with weight 0 are left to the animation.
+    /// Missing entries weigh 1
+    #[export]
+    bone_weights: PackedFloat32Array,
+
+    base: Base<SkeletonModifier3D>,
+    // Jacobian of the last frame (see `jacobian_shape`)
+    jacobian: Vec<f32>,
+    // indices of active bones
+    active_bones: Vec<i32>,
+    // target poses set from script. Take precedence over the target nodes
+    main_target_transform: Option<Transform3D>,
+    default_main_target_transform: Option<Transform3D>,
+    secondary_target_transform: Option<Transform3D>,
+    default_secondary_target_transform: Option<Transform3D>,
+    main_filter_state: FilterState,
+    secondary_filter_state: FilterState,
+    main_history: MotionHistory,
+    secondary_history: MotionHistory,
+    // local bone poses before this modifier (for `layer_on_animation`)
+    incoming: Vec<Transform3D>,
+    // recording in progress and the recording played back
+    recorder: Option<Recording>,
+    recording_time: f32,
+    player: Recording,
+    playback_time: f32,
+    contacts: Vec<FootContact>,
+    // whether to plant all feet in the next physics frame
+    plant_pending: bool,
+    debug_mesh: Option<Gd<ImmediateMesh>>,
+    rate_limiter: RateLimiter,
+    // state for emitting signals on changes
+    reached: Vec<GString>,
+    fallen_back: Vec<GString>,
+    fallbacks: Vec<GString>,
+    converged: bool,
+    at_limit: Vec<bool>,
+    main_blend: TargetBlend,
+    secondary_blend: TargetBlend,
+    main_reach_check: ReachCheck,
+    secondary_reach_check: ReachCheck,
+    // time of the last frame in microseconds
+    last_ticks: u64,
+    // residual and frames since the last progress for detecting stalls
+    best_residual: f32,
+    stalled_frames: i32,
+    restart: Option<Restart>,
+    // whether the skeleton has the effectors of `method`, checked by `update_mannequin`
+    effectors_found: bool,
+}
+
+impl RsMannequinIK {
+    /// Index of the main effector among the effectors. With two effectors, the secondary
+    /// effector comes first: `solve_secondary_goals` expects the rows of the main effector,
+    /// which has priority, in the second block of the Jacobian
+    fn main_slot(&self) -> usize {
+        if matches!(self.method, Method::Secondary | Method::Grasp) {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn log_enabled(&self, category: Category, level: Verbosity) -> bool {
+        enabled(self.verbosity, self.log_categories, category, level)
+            && self.rate_limiter.allow(category, self.log_interval)
+    }
+
+    pub(crate) fn active_bones(&self) -> &[i32] {
+        &self.active_bones
+    }
+
+    /// Lower and upper limit of a joint if it is limited
+    pub(crate) fn limits(&self, idx: usize) -> Option<(f32, f32)> {
+        self.joint_limits
+            .as_slice()
+            .get(idx)
+            .filter(|limits| limits.x < limits.y)
+            .map(|limits| (limits.x, limits.y))
+    }
+
+    pub(crate) fn joint_limits(&self) -> PackedVector2Array {
+        self.joint_limits.clone()
+    }
+
+    pub(crate) fn set_limits(&mut self, idx: usize, lower: f32, upper: f32) {
+        if self.joint_limits.len() <= idx {
+            self.joint_limits.resize(idx + 1);
+        }
+        self.joint_limits.as_mut_slice()[idx] = Vector2::new(lower, upper);
+    }
+
+    /// Returns the bones that just hit a limit
+    fn clamp_angles(&mut self) -> Vec<usize> {
+        self.at_limit.resize(self.angles.len(), false);
+        (0..self.angles.len())
+            .filter(|idx| {
+                let limited = self.limits(*idx).is_some_and(|(lower, upper)| {
+                    let angle = self.angles[*idx];
+                    self.angles[*idx] = angle.clamp(lower, upper);
+                    angle != self.angles[*idx]
+                });
+                let hit = limited && !self.at_limit[*idx];
+                self.at_limit[*idx] = limited;
+                hit
+            })
+            .collect_vec()
+    }
+
+    /// Limits of the active bones as bounds of the optimizer (unbounded if not limited)
+    fn bounds(&self) -> Vec<(f32, f32)> {
+        self.active_bones
+            .iter()
+            .map(|idx| {
+                self.limits(*idx as usize)
+                    .unwrap_or((f32::NEG_INFINITY, f32::INFINITY))
+            })
+            .collect()
+    }
+
+    /// Uniformly distributed angle within the limits of a joint (or a full turn)
+    fn random_angle(&self, idx: usize) -> f32 {
+        let (lower, upper) = self.limits(idx).unwrap_or((-PI, PI));
+        randf_range(lower as f64, upper as f64) as f32
+    }
+
+    /// Bone of the main effector, from its attachment if there is one
+    fn main_effector_name(&self) -> GString {
+        self.main_effector_attachment
+            .as_ref()
+            .map_or(self.main_effector.clone(), |attachment| {
+                attachment.get_bone_name()
+            })
+    }
+
+    fn secondary_effector_name(&self) -> GString {
+        self.secondary_effector_attachment
+            .as_ref()
+            .map_or(self.secondary_effector.clone(), |attachment| {
+                attachment.get_bone_name()
+            })
+    }
+
+    /// Whether the skeleton has all the effectors `method` needs. Logs an error otherwise, so the
+    /// solver uses the result cached by `update_mannequin` (`effectors_found`)
+    fn has_effectors(&self, skeleton: &Gd<Skeleton3D>) -> bool {
+        let bones = self.effector_bones(skeleton);
+        let count = bones.iter().filter(|bone| **bone != -1).count();
+        let result = count == bones.len();
+        if !result {
+            ik_log!(
+                self,
+                Setup,
+                Error,
+                "{:?} needs {} effectors, found {count}",
+                self.method,
+                bones.len()
+            );
+        }
+        result
+    }
+
+    /// Rows of the Jacobian per effector: the position and, with `Method::Orientation`, the
+    /// scaled axis of the orientation
+    fn effector_rows(&self) -> usize {
+        if matches!(self.method, Method::Orientation) {
+            6
+        } else {
+            3
+        }
+    }
+
+    /// Rows (effector coordinates) and columns (active joints) of the Jacobian
+    fn jacobian_shape(&self) -> (usize, usize) {
+        (
+            self.effector_rows() * (self.main_slot() + 1),
+            self.active_bones.len(),
+        )
+    }
+
+    /// Kinematics of the skeleton relative to the poses the angles apply to, masked by
+    /// `bone_weights`
+    fn chain(&self, skeleton: &Gd<Skeleton3D>) -> Chain {
+        Chain::new(
+            skeleton,
+            &self.base_poses(skeleton),
+            self.bone_weights.as_slice(),
+        )
+    }
+
+    /// All angles with the active ones replaced by `parameters`
+    fn expand(&self, angles: &[f32], parameters: &[f32]) -> Vec<f32> {
+        let mut angles = angles.to_vec();
+        self.active_bones
+            .iter()
+            .zip(parameters)
+            .for_each(|(idx, parameter)| angles[*idx as usize] = *parameter);
+        angles
+    }
+
+    /// Angles of the active bones
+    fn parameters(&self, angles: &[f32]) -> Vec<f32> {
+        self.active_bones
+            .iter()
+            .map(|idx| angles[*idx as usize])
+            .collect_vec()
+    }
+
+    /// Residuals of points towards their goals and the Jacobian of the points for `angles` (all
+    /// bones). Each point is a bone, an offset in its local coordinates and a goal (skeleton
+    /// coordinates)
+    fn evaluate_points(
+        &self,
+        chain: &Chain,
+        points: &[(usize, Vector3, Vector3)],
+        angles: &[f32],
+    ) -> Evaluation {
+        let poses = chain.poses(angles);
+        let rows = 3 * points.len();
+        let cols = self.active_bones.len();
+        let mut jacobian = vec![0f32; rows * cols];
+        points
+            .iter()
+            .enumerate()
+            .for_each(|(point, (bone, offset, _))| {
+                poses
+                    .point_jacobian(&self.active_bones, *bone, *offset)
+                    .chunks(3)
+                    .enumerate()
+                    .for_each(|(col, column)| {
+                        jacobian[col * rows + 3 * point..col * rows + 3 * point + 3]
+                            .copy_from_slice(column);
+                    });
+            });
+        Evaluation {
+            residual: points
+                .iter()
+                .flat_map(|(bone, offset, goal)| (*goal - poses.point(*bone, *offset)).to_array())
+                .collect(),
+            jacobian,
+            rows,
+            cols,
+        }
+    }
+
+    /// Bones of the effectors in effector order (see `main_slot`)
+    fn effector_bones(&self, skeleton: &Gd<Skeleton3D>) -> Vec<i32> {
+        let main = skeleton.find_bone(&self.main_effector_name());
+        if matches!(self.method, Method::Secondary | Method::Grasp) {
+            vec![skeleton.find_bone(&self.secondary_effector_name()), main]
+        } else {
+            vec![main]
+        }
+    }
+
+    /// Tool center points (relative to the effector bones) in effector order (see `main_slot`)
+    fn tool_offsets(&self) -> Vec<Transform3D> {
+        if matches!(self.method, Method::Secondary | Method::Grasp) {
+            vec![self.secondary_tcp, self.main_tcp]
+        } else {
+            vec![self.main_tcp]
+        }
+    }
+
+    /// Positions of the tool center points (skeleton coordinates) in the order of the model's
+    /// effectors and their Jacobian (see `jacobian_shape`). Expects all effectors to exist
+    fn tool_points(&self, skeleton: &Gd<Skeleton3D>, chain: &Chain) -> (Vec<Vector3>, Vec<f32>) {
+        let (rows, cols) = self.jacobian_shape();
+        let mut jacobian = vec![0f32; rows * cols];
+        let poses = chain.poses(&self.angles);
+
+        let mut points = vec![];
+        for (slot, (bone, tcp)) in self
+            .effector_bones(skeleton)
+            .into_iter()
+            .zip(self.tool_offsets())
+            .enumerate()
+        {
+            let bone = bone as usize;
+            let row = slot * self.effector_rows();
+            points.push(poses.point(bone, tcp.origin));
+            poses
+                .point_jacobian(&self.active_bones, bone, tcp.origin)
+                .chunks(3)
+                .enumerate()
+                .for_each(|(col, column)| {
+                    jacobian[col * rows + row..col * rows + row + 3].copy_from_slice(column);
+                });
+            if self.effector_rows() == 6 {
+                // The orientation changes by the angular velocity of the joints moving the bone
+                poses
+                    .rotation_jacobian(&self.active_bones, bone)
+                    .chunks(3)
+                    .enumerate()
+                    .for_each(|(col, column)| {
+                        jacobian[col * rows + row + 3..col * rows + row + 6]
+                            .copy_from_slice(column);
+                    });
+            }
+        }
+        (points, jacobian)
+    }
+
+    /// Orientations of the tool center points (skeleton coordinates) in the order of the model's
+    /// effectors and their angular Jacobians (`3 * effectors x cols`). Expects all effectors to
+    /// exist
+    fn tool_rotations(
+        &self,
+        skeleton: &Gd<Skeleton3D>,
+        poses: &BonePoses,
+    ) -> (Vec<Basis>, Vec<f32>) {
+        let bones = self.effector_bones(skeleton);
+        let rows = 3 * bones.len();
+        let mut jacobian = vec![0f32; rows * self.active_bones.len()];
+
+        let mut bases = vec![];
+        for (slot, (bone, tcp)) in bones.into_iter().zip(self.tool_offsets()).enumerate() {
+            let bone = bone as usize;
+            bases.push(poses.poses[bone].basis * tcp.basis);
+            poses
+                .rotation_jacobian(&self.active_bones, bone)
+                .chunks(3)
+                .enumerate()
+                .for_each(|(col, column)| {
+                    jacobian[col * rows + 3 * slot..col * rows + 3 * slot + 3]
+                        .copy_from_slice(column);
+                });
+        }
+        (bases, jacobian)
+    }
+
+    /// Optimizes the tool center point of the main or the secondary effector towards `target`
+    /// (global coordinates) on a scratch copy of the angles. Returns the closest reachable
+    /// position (global coordinates)
+    fn reach(&self, secondary: bool, target: Vector3) -> Option<(Vector3, OptimizationResult)> {
+        let (effector, tcp) = if secondary {
+            (self.secondary_effector_name(), self.secondary_tcp.origin)
+        } else {
+            (self.main_effector_name(), self.main_tcp.origin)
+        };
+        let skeleton = self.base().get_skeleton()?;
+        let bone = usize::try_from(skeleton.find_bone(&effector)).ok()?;
+        let global = skeleton.get_global_transform();
+        let target = global.affine_inverse() * target;
+
+        let chain = self.chain(&skeleton);
+        let options = OptimizationOptions {
+            optimizer: self.optimizer,
+            bounds: self.bounds(),
+            tolerance: self.reach_tolerance,
+            ..Default::default()
+        };
+
+        let result = optimize(
+            |parameters| {
+                self.evaluate_points(
+                    &chain,
+                    &[(bone, tcp, target)],
+                    &self.expand(&self.angles, parameters),
+                )
+            },
+            &self.parameters(&self.angles),
+            &options,
+        );
+        let closest = global
+            * chain
+                .poses(&self.expand(&self.angles, &result.parameters))
+                .point(bone, tcp);
+        Some((closest, result))
+    }
+
+    /// Whether `target` (global coordinates) at `distance` from the main or the secondary
+    /// effector is out of its reach
+    fn out_of_reach(&mut self, secondary: bool, target: Vector3, distance: f32, dt: f32) -> bool {
+        if !self.reachability_fallback {
+            return distance > self.min_dist;
+        }
+        let mut check = if secondary {
+            std::mem::take(&mut self.secondary_reach_check)
+        } else {
+            std::mem::take(&mut self.main_reach_check)
+        };
+        let result = check.out_of_reach(
+            target,
+            dt,
+            self.reach_check_interval,
+            self.reach_tolerance,
+            || {
+                self.reach(secondary, target)
+                    .is_none_or(|(_, result)| result.residual > self.reach_tolerance)
+            },
+        );
+        if secondary {
+            self.secondary_reach_check = check;
+        } else {
+            self.main_reach_check = check;
+        }
+        result
+    }
+
+    /// Seconds since the last call
+    fn frame_time(&mut self) -> f32 {
+        let now = Time::singleton().get_ticks_usec();
+        let dt = if self.last_ticks == 0 {
+            0.0
+        } else {
+            (now - self.last_ticks) as f32 * 1e-6
+        };
+        self.last_ticks = now;
+        dt
+    }
+
+    /// Passes the pose of the main or the secondary target through its filter
+    fn filter_target(&mut self, secondary: bool, pose: Transform3D, dt: f32) -> Transform3D {
+        let (filter, state) = if secondary {
+            (
+                self.secondary_target_filter.clone(),
+                &mut self.secondary_filter_state,
+            )
+        } else {
+            (self.main_target_filter.clone(), &mut self.main_filter_state)
+        };
+        match filter {
+            Some(filter) => state.apply(&filter.bind().settings(), pose, dt),
+            None => pose,
+        }
+    }
+
+    /// Extrapolates the pose of the main or the secondary target by `latency`. Also returns the
+    /// estimated motion of the target (zero without `predict_motion`)
+    fn predict_target(
+        &mut self,
+        secondary: bool,
+        pose: Transform3D,
+        dt: f32,
+    ) -> (Transform3D, Motion) {
+        let history = if secondary {
+            &mut self.secondary_history
+        } else {
+            &mut self.main_history
+        };
+        if !self.predict_motion {
+            history.clear();
+            return (pose, Motion::default());
+        }
+        history.push(pose, dt, self.prediction_window);
+        let motion = history.motion();
+        ik_log!(
+            self,
+            Targets,
+            Debug,
+            "velocity: {} m/s, {} rad/s",
+            motion.linear,
+            motion.angular.length()
+        );
+        (motion.extrapolate(pose, self.latency), motion)
+    }
+
+    /// Goal (global coordinates) of the main or the secondary effector: its target blended with
+    /// its default target according to `blend_policy`. `position` is the tool center point
+    /// (skeleton coordinates)
+    fn goal(
+        &mut self,
+        secondary: bool,
+        live: Transform3D,
+        position: Vector3,
+        to_skeleton: Transform3D,
+        dt: f32,
+    ) -> Transform3D {
+        let (effector, default) = if secondary {
+            (
+                self.secondary_effector_name(),
+                self.default_secondary_target_pose(),
+            )
+        } else {
+            (self.main_effector_name(), self.default_main_target_pose())
+        };
+
+        let distance = (to_skeleton * live.origin - position).length();
+
+        let Some(default) = default else {
+            if distance > self.min_dist {
+                ik_log!(self, Targets, Warning, "No default target for `{effector}`");
+            }
+            return live;
+        };
+        // Only some policies depend on the (possibly expensive) reachability check
+        let out_of_reach = self.blend_policy.uses_reach()
+            && self.out_of_reach(secondary, live.origin, distance, dt);
+
+        let settings = BlendSettings {
+            policy: self.blend_policy,
+            min_dist: self.min_dist,
+            falloff: self.blend_falloff,
+            fade_time: self.blend_fade_time,
+            hysteresis: self.blend_hysteresis,
+        };
+        let blend = if secondary {
+            &mut self.secondary_blend
+        } else {
+            &mut self.main_blend
+        };
+        let weight = blend.update(&settings, out_of_reach, distance, dt);
+        if weight > 0.0 {
+            self.fallbacks.push(effector);
+        }
+
+        live.interpolate_with(&default, weight)
+    }
+
+    /// Names of the effectors in effector order (see `main_slot`)
+    fn effector_names(&self) -> Vec<GString> {
+        if matches!(self.method, Method::Secondary | Method::Grasp) {
+            vec![self.secondary_effector_name(), self.main_effector_name()]
+        } else {
+            vec![self.main_effector_name()]
+        }
+    }
+
+    /// Compares the state of this frame with the previous one and returns the signals to emit
+    fn lifecycle_signals(
+        &mut self,
+        skeleton: &Gd<Skeleton3D>,
+        effectors: &[Vector3],
+        update: &[f32],
+        limited: &[usize],
+    ) -> Vec<(&'static str, Vec<Variant>)> {
+        let mut signals = vec![];
+        let to_skeleton = skeleton.get_global_transform().affine_inverse();
+
+        let reached = self
+            .effector_names()
+            .into_iter()
+            .zip(self.targets())
+            .zip(effectors)
+            .filter_map(|((name, target), effector)| {
+                let target = to_skeleton * target?.origin;
+                ((target - *effector).length() <= self.reach_tolerance).then_some(name)
+            })
+            .collect_vec();
+        reached
+            .iter()
+            .filter(|name| !self.reached.contains(name))
+            .for_each(|name| signals.push(("target_reached", vec![name.to_variant()])));
+        self.reached
+            .iter()
+            .filter(|name| !reached.contains(name))
+            .for_each(|name| signals.push(("target_lost", vec![name.to_variant()])));
+        self.reached = reached;
+
+        let fallen_back = std::mem::take(&mut self.fallbacks);
+        fallen_back
+            .iter()
+            .filter(|name| !self.fallen_back.contains(name))
+            .for_each(|name| signals.push(("fell_back_to_default", vec![name.to_variant()])));
+        self.fallen_back = fallen_back;
+
+        let converged = update.iter().map(|x| x.powi(2)).sum::<f32>().sqrt() < 1e-4;
+        if converged && !self.converged {
+            signals.push(("converged", vec![]));
+        }
+        self.converged = converged;
+
+        limited.iter().for_each(|idx| {
+            signals.push((
+                "joint_limit_hit",
+                vec![skeleton.get_bone_name(*idx as i32).to_variant()],
+            ))
+        });
+
+        signals
+    }
+
+    /// Row of the main effector in the Jacobian
+    fn main_offset(&self) -> usize {
+        self.main_slot() * self.effector_rows()
+    }
+
+    /// Targets in effector order (see `main_slot`)
+    fn targets(&self) -> Vec<Option<Transform3D>> {
+        let main = self.main_target_pose();
+        if matches!(self.method, Method::Secondary | Method::Grasp) {
+            vec![self.secondary_target_pose(), main]
+        } else {
+            vec![main]
+        }
+    }
+
+    /// Pose set from script or else the global transform of the node
+    fn target_pose(node: &Option<Gd<Node3D>>, pose: Option<Transform3D>) -> Option<Transform3D> {
+        pose.or_else(|| node.as_ref().map(|node| node.get_global_transform()))
+    }
+
+    fn main_target_pose(&self) -> Option<Transform3D> {
+        Self::target_pose(&self.main_target, self.main_target_transform)
+    }
+
+    fn default_main_target_pose(&self) -> Option<Transform3D> {
+        Self::target_pose(
+            &self.default_main_target,
+            self.default_main_target_transform,
+        )
+    }
+
+    fn secondary_target_pose(&self) -> Option<Transform3D> {
+        Self::target_pose(&self.secondary_target, self.secondary_target_transform)
+    }
+
+    fn default_secondary_target_pose(&self) -> Option<Transform3D> {
+        Self::target_pose(
+            &self.default_secondary_target,
+            self.default_secondary_target_transform,
+        )
+    }
+
+    /// Replaces the pose set from script of the main or the secondary (default) target. Switching
+    /// between the node and a pose resets the filter and the motion history of the effector, as
+    /// its goal jumps
+    fn replace_target_transform(
+        &mut self,
+        secondary: bool,
+        default: bool,
+        transform: Option<Transform3D>,
+    ) {
+        let pose = match (secondary, default) {
+            (false, false) => &mut self.main_target_transform,
+            (false, true) => &mut self.default_main_target_transform,
+            (true, false) => &mut self.secondary_target_transform,
+            (true, true) => &mut self.default_secondary_target_transform,
+        };
+        let switched = pose.is_some() != transform.is_some();
+        *pose = transform;
+        if switched {
+            self.reset_target_tracking(secondary);
+        }
+    }
+
+    /// Forgets the filtered pose and the motion of the main or the secondary target, such that
+    /// a jump of the target (e.g., a new target node) is not taken for a fast motion
+    fn reset_target_tracking(&mut self, secondary: bool) {
+        if secondary {
+            self.secondary_filter_state = FilterState::default();
+            self.secondary_history.clear();
+        } else {
+            self.main_filter_state = FilterState::default();
+            self.main_history.clear();
+        }
+    }
+
+    fn debug_lines(&self, skeleton: &Gd<Skeleton3D>) -> DebugLines {
+        let mut lines = DebugLines::default();
+        let global = skeleton.get_global_transform();
+        let poses = BonePoses::from_skeleton(skeleton);
+        let size = self.debug_size;
+
+        self.active_bones.iter().for_each(|idx| {
+            let pose = global * poses.poses[*idx as usize];
+            lines.arrow(pose.origin, pose.basis * Vector3::BACK * size, JOINT_AXIS);
+        });
+
+        self.effector_bones(skeleton)
+            .iter()
+            .zip(self.targets())
+            .zip(self.tool_offsets())
+            .filter(|((idx, _), _)| **idx != -1)
+            .for_each(|((idx, target), tcp)| {
+                let pose = global * poses.poses[*idx as usize] * tcp;
+                lines.frame(pose, size);
+                if let Some(target) = target {
+                    lines.frame(target, size);
+                    lines.line(pose.origin, target.origin, RESIDUAL);
+                }
+            });
+
+        // How each joint moves the main effector
+        let main = skeleton.find_bone(&self.main_effector_name());
+        let (rows, cols) = self.jacobian_shape();
+        if main != -1 && rows > 0 && self.jacobian.len() == rows * cols {
+            let origin = global * poses.poses[main as usize].origin;
+            let offset = self.main_offset();
+            self.jacobian.chunks(rows).for_each(|col| {
+                let direction = Vector3::new(col[offset], col[offset + 1], col[offset + 2]);
+                lines.arrow(origin, global.basis * direction * size, JACOBIAN);
+            });
+        }
+
+        lines
+    }
+
+    /// The mesh of the debug overlay. Created on first use
+    fn debug_mesh(&mut self) -> Gd<ImmediateMesh> {
+        if let Some(mesh) = &self.debug_mesh {
+            return mesh.clone();
+        }
+        let mesh = ImmediateMesh::new_gd();
+        let mut instance = MeshInstance3D::new_alloc();
+        instance.set_mesh(&mesh);
+        // Lines are in global coordinates
+        instance.set_as_top_level(true);
+        self.base_mut()
+            .call_deferred("add_child", &[instance.to_variant()]);
+        self.debug_mesh = Some(mesh.clone());
+        mesh
+    }
+
+    /// Counts the frames in which the residual did not improve. Returns true once the solver
+    /// counts as stuck
+    fn is_stalled(&mut self, residual: f32) -> bool {
+        // Close enough, nothing to escape from
+        if residual < 1e-2 {
+            self.best_residual = residual;
+            self.stalled_frames = 0;
+            return false;
+        }
+        if residual < self.best_residual - 1e-3 {
+            self.best_residual = residual;
+            self.stalled_frames = 0;
+        } else {
+            self.stalled_frames += 1;
+        }
+        self.stalled_frames >= self.stall_frames
+    }
+
+    /// Starts a restart from all seed configurations (see `restart_from_seeds`)
+    fn start_restart(&mut self) {
+        self.stalled_frames = 0;
+        self.best_residual = f32::MAX;
+
+        let mut seeds = self
+            .seed_poses
+            .iter_shared()
+            .map(|pose| pose.to_vec())
+            .filter(|pose| pose.len() == self.angles.len())
+            .collect_vec();
+        seeds.extend((0..self.seed_count).map(|_| {
+            let mut seed = self.angles.clone();
+            self.active_bones.iter().for_each(|idx| {
+                seed[*idx as usize] = self.random_angle(*idx as usize);
+            });
+            seed
+        }));
+        self.restart = Some(Restart {
+            count: seeds.len(),
+            seeds,
+            best: None,
+        });
+    }
+
+    /// Optimizes the next seed of the restart in progress towards `goals` (tool center points in
+    /// skeleton coordinates, in effector order (see `main_slot`)). One seed per frame keeps
+    /// the frame time bounded. After the last seed, the best result replaces the angles if it is
+    /// closer to the goals
+    fn restart_from_seeds(&mut self, skeleton: &Gd<Skeleton3D>, goals: &[Vector3]) {
+        let Some(mut restart) = self.restart.take() else {
+            return;
+        };
+        let points = self
+            .effector_bones(skeleton)
+            .into_iter()
+            .zip(self.tool_offsets())
+            .zip(goals)
+            .map(|((bone, tcp), goal)| (bone as usize, tcp.origin, *goal))
+            .collect_vec();
+        let chain = self.chain(skeleton);
+
+        if let Some(seed) = restart.seeds.pop() {
+            let options = OptimizationOptions {
+                optimizer: self.optimizer,
+                bounds: self.bounds(),
+                ..Default::default()
+            };
+            let result = optimize(
+                |parameters| self.evaluate_points(&chain, &points, &self.expand(&seed, parameters)),
+                &self.parameters(&seed),
+                &options,
+            );
+            if restart
+                .best
+                .as_ref()
+                .is_none_or(|(_, residual)| result.residual < *residual)
+            {
+                restart.best = Some((self.expand(&seed, &result.parameters), result.residual));
+            }
+        }
+        if !restart.seeds.is_empty() {
+            self.restart = Some(restart);
+            return;
+        }
+        let Some((angles, _)) = restart.best else {
+            return;
+        };
+
+        // The goals may have moved since the best seed was optimized
+        let residual = |angles: &[f32]| {
+            self.evaluate_points(&chain, &points, angles)
+                .residual_norm()
+        };
+        let (current, restarted) = (residual(&self.angles), residual(&angles));
+        ik_log!(
+            self,
+            Solver,
+            Info,
+            "Restarted from {} seeds. Residual: {} -> {}",
+            restart.count,
+            current,
+            restarted
+        );
+        if restarted < current {
+            self.angles = angles;
+            self.clamp_angles();
+        }
+    }
+
+    /// Appends the current angles, the goals (skeleton coordinates, in the order of the model's
+    /// effectors) and the residuals to the recording in progress
+    fn record_frame(&mut self, dt: f32, goals: &[Option<Vector3>], effectors: &[Vector3]) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        self.recording_time += dt;
+        recorder.frames.push(Frame {
+            time: self.recording_time,
+            angles: self.angles.clone(),
+            targets: goals.to_vec(),
+            // 0 without a goal
+            residuals: goals
+                .iter()
+                .zip(effectors)
+                .map(|(goal, effector)| goal.map_or(0.0, |goal| (goal - *effector).length()))
+                .collect(),
+        });
+    }
+
+    /// Applies the angles of the recording at the current playback time
+    fn play_frame(&mut self, skeleton: &mut Gd<Skeleton3D>) {
+        let dt = self.frame_time();
+        self.playback_time += dt;
+        let Some(angles) = self.player.angles_at(self.playback_time) else {
+            return;
+        };
+        if angles.len() != self.angles.len() {
+            ik_log!(
+                self,
+                Pose,
+                Error,
+                "Recording has {} angles, expected {}",
+                angles.len(),
+                self.angles.len()
+            );
+            return;
+        }
+        self.angles = angles.to_vec();
+        self.apply_angles(skeleton);
+    }
+
+    /// Local poses the angles are relative to: the incoming poses with `layer_on_animation`, else
+    /// the rest poses
+    fn base_poses(&self, skeleton: &Gd<Skeleton3D>) -> Vec<Transform3D> {
+        let count = skeleton.get_bone_count();
+        if self.layer_on_animation && self.incoming.len() == count as usize {
+            self.incoming.clone()
+        } else {
+            (0..count).map(|idx| skeleton.get_bone_rest(idx)).collect()
+        }
+    }
+
+    /// Sets the bone poses from the angles. The joints rotate relative to the rest (or incoming)
+    /// pose such that poses do not compound over frames
+    fn apply_angles(&self, skeleton: &mut Gd<Skeleton3D>) {
+        let chain = self.chain(skeleton);
+        self.angles.iter().enumerate().for_each(|(idx, angle)| {
+            skeleton.set_bone_pose(idx as i32, chain.local(idx, *angle));
+        });
+    }
+
+    /// Update the mannequin-related structures
+    ///
+    /// TODO figure out where to call it for hot-reloading
+    fn update_mannequin(&mut self) {
+        ik_log!(
+            self,
+            Setup,
+            Info,
+            "Main: `{}`, secondary: `{}`",
+            self.main_effector_name(),
+            self.secondary_effector_name()
+        );
+        self.effectors_found = false;
+        if let Some(skeleton) = self.base().get_skeleton() {
+            let main_effector_idx = skeleton.find_bone(&self.main_effector_name());
+            if main_effector_idx == -1 {
+                ik_log!(
+                    self,
+                    Setup,
+                    Error,
+                    "Could not find `{}` in skeleton",
+                    self.main_effector_name()
+                );
+                return;
+            }
+
+            ik_log!(self, Setup, Debug, "main effector: `{main_effector_idx}`");
+
+            let secondary_effector_idx = skeleton.find_bone(&self.secondary_effector_name());
+            if secondary_effector_idx != -1 {
+                ik_log!(
+                    self,
+                    Setup,
+                    Debug,
+                    "secondary effector: `{secondary_effector_idx}`"
+                );
+            } else {
+                ik_log!(
+                    self,
+                    Setup,
+                    Warning,
+                    "Could not find `{}` in skeleton",
+                    self.secondary_effector_name()
+                );
+            };
+
+            self.active_bones = (0..skeleton.get_bone_count())
+                .filter(|idx| skeleton.is_bone_enabled(*idx))
+                .collect_vec();
+
+            // Not yet supported
+
+            // Angles must be computed for all joints!
+            self.angles = vec![0.0; skeleton.get_bone_count() as usize];
+            self.restart = None;
+
+            ik_log!(
+                self,
+                Setup,
+                Info,
+                "Active bones (joints): {:?}",
+                self.active_bones
+                    .iter()
+                    .map(|idx| skeleton.get_bone_name(*idx))
+                    .collect_vec()
+            );
+
+            self.contacts = self
+                .feet
+                .as_slice()
+                .iter()
+                .filter_map(|name| {
+                    let idx = skeleton.find_bone(name);
+                    if idx == -1 {
+                        ik_log!(
+                            self,
+                            Setup,
+                            Warning,
+                            "Could not find foot `{name}` in skeleton"
+                        );
+                        None
+                    } else {
+                        Some(FootContact::new(name.clone(), idx))
+                    }
+                })
+                .collect_vec();
+
+            // The physics space may only be queried from the physics thread
+            self.plant_pending = self.plant_feet;
+
+            self.effectors_found = self.has_effectors(&skeleton);
+
+            ik_log!(
+                self,
+                Setup,
+                Info,
+                "Setup. Jacobian shape: {:?}",
+                self.jacobian_shape()
+            );
+        } else {
+            ik_log!(self, Setup, Error, "No skeleton found");
+        }
+    }
+}
+
+#[godot_api]
+impl RsMannequinIK {
+    /// An effector got within `reach_tolerance` of its target
+    #[signal]
+    fn target_reached(effector: GString);
+
+    /// An effector is no longer within `reach_tolerance` of its target
+    #[signal]
+    fn target_lost(effector: GString);
+
+    /// The default target of an effector started to take over (its blend weight became non-zero)
+    #[signal]
+    fn fell_back_to_default(effector: GString);
+
+    /// The updates of the angles became negligible
+    #[signal]
+    fn converged();
+
+    /// A joint reached one of its limits
+    #[signal]
+    fn joint_limit_hit(bone: GString);
+
+    /// Changing the end-effectors requires recomputing the tree
+    #[func]
+    pub fn set_main_effector(&mut self, value: GString) {
+        self.main_effector = value;
+        self.update_mannequin();
+    }
+
+    /// Changing the end-effectors requires recomputing the tree
+    #[func]
+    pub fn set_secondary_effector(&mut self, value: GString) {
+        self.secondary_effector = value;
+        self.update_mannequin();
+    }
+
+    /// A new target node restarts filtering and motion prediction
+    #[func]
+    pub fn set_main_target(&mut self, value: Option<Gd<Node3D>>) {
+        self.main_target = value;
+        self.reset_target_tracking(false);
+    }
+
+    #[func]
+    pub fn set_default_main_target(&mut self, value: Option<Gd<Node3D>>) {
+        self.default_main_target = value;
+        self.reset_target_tracking(false);
+    }
+
+    #[func]
+    pub fn set_secondary_target(&mut self, value: Option<Gd<Node3D>>) {
+        self.secondary_target = value;
+        self.reset_target_tracking(true);
+    }
+
+    #[func]
+    pub fn set_default_secondary_target(&mut self, value: Option<Gd<Node3D>>) {
+        self.default_secondary_target = value;
+        self.reset_target_tracking(true);
+    }
+
+    #[func]
+    pub fn set_main_effector_attachment(&mut self, value: Option<Gd<BoneAttachment3D>>) {
+        self.main_effector_attachment = value;
+        self.update_mannequin();
+    }
+
+    #[func]
+    pub fn set_secondary_effector_attachment(&mut self, value: Option<Gd<BoneAttachment3D>>) {
+        self.secondary_effector_attachment = value;
+        self.update_mannequin();
+    }
+
+    #[func]
+    pub fn set_method(&mut self, value: Method) {
+        self.method = value;
+        ik_log!(self, Setup, Info, "Updated method");
+        self.update_mannequin();
+    }
+
+    /// Starts capturing angles, goals and residuals every frame. Discards a recording in progress
+    #[func]
+    pub fn start_recording(&mut self) {
+        let bones = match self.base().get_skeleton() {
+            Some(skeleton) => (0..skeleton.get_bone_count())
+                .map(|idx| skeleton.get_bone_name(idx).to_string())
+                .collect(),
+            None => vec![],
+        };
+        self.recorder = Some(Recording {
+            bones,
+            frames: vec![],
+        });
+        self.recording_time = 0.0;
+    }
+
+    /// Stops capturing and writes the recording to `recording_path`, even if it has no frames.
+    /// Returns false if not recording or the file could not be written
+    #[func]
+    pub fn stop_recording(&mut self) -> bool {
+        let Some(recording) = self.recorder.take() else {
+            godot_warn!("Not recording");
+            return false;
+        };
+        if !recording.save(&self.recording_path) {
+            godot_error!("Could not write `{}`", self.recording_path);
+            return false;
+        }
+        godot_print!(
+            "Recorded {} frames ({} s) to `{}`",
+            recording.frames.len(),
+            recording.duration(),
+            self.recording_path
+        );
+        true
+    }
+
+    /// Loads a recording (CSV or JSON) and drives the skeleton from it instead of solving.
+    /// Returns false if the file could not be read
+    #[func]
+    pub fn start_playback(&mut self, path: GString) -> bool {
+        let Some(recording) = Recording::load(&path) else {
+            godot_error!("Could not read recording `{path}`");
+            return false;
+        };
+        if recording.bones.len() != self.angles.len() {
+            godot_warn!(
+                "Recording has {} bones, skeleton {}",
+                recording.bones.len(),
+                self.angles.len()
+            );
+        }
+        self.player = recording;
+        self.playback_time = 0.0;
+        self.playback = true;
+        true
+    }
+
+    /// Returns to solving
+    #[func]
+    pub fn stop_playback(&mut self) {
+        self.playback = false;
+    }
+
+    /// Stores the current transform of the secondary relative to the main effector as the grasp
+    /// that `Method::Grasp` maintains
+    #[func]
+    pub fn capture_grasp(&mut self) {
+        if !matches!(self.method, Method::Grasp) {
+            godot_warn!("Capturing a grasp requires the `Grasp` method");
+            return;
+        }
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return;
+        };
+        // Same effector order as in `Method::Secondary`
+        if let Some(name) = [self.secondary_effector_name(), self.main_effector_name()]
+            .into_iter()
+            .find(|name| skeleton.find_bone(name) == -1)
+        {
+            godot_warn!("Cannot capture a grasp, effector bone `{name}` not found");
+            return;
+        }
+        let chain = self.chain(&skeleton);
+        let (effectors, _) = self.tool_points(&skeleton, &chain);
+        let (bases, _) = self.tool_rotations(&skeleton, &chain.poses(&self.angles));
+        self.grasp_offset = bases[1].inverse() * (effectors[0] - effectors[1]);
+        self.grasp_rotation = bases[1].inverse() * bases[0];
+        godot_print!(
+            "Captured grasp offset: {:?}, rotation: {:?}",
+            self.grasp_offset,
+            self.grasp_rotation
+        );
+    }
+
+    /// Angles of all bones (by index) in radians
+    #[func]
+    pub fn get_angles(&self) -> PackedFloat32Array {
+        PackedFloat32Array::from(self.angles.as_slice())
+    }
+
+    /// Sets the angles of all bones (by index) and applies them. Returns false if the number of
+    /// angles does not match the skeleton
+    #[func]
+    pub fn set_angles(&mut self, angles: PackedFloat32Array) -> bool {
+        if angles.len() != self.angles.len() {
+            godot_error!(
+                "Got {} angles, expected {}",
+                angles.len(),
+                self.angles.len()
+            );
+            return false;
+        }
+        self.angles = angles.to_vec();
+
+        if let Some(mut skeleton) = self.base().get_skeleton() {
+            self.apply_angles(&mut skeleton);
+        }
+        true
+    }
+
+    /// Angles by bone name
+    #[func]
+    pub fn get_bone_angles(&self) -> Dictionary {
+        let mut result = Dictionary::new();
+        if let Some(skeleton) = self.base().get_skeleton() {
+            self.angles.iter().enumerate().for_each(|(idx, angle)| {
+                result.set(skeleton.get_bone_name(idx as i32), *angle);
+            });
+        }
+        result
+    }
+
+    /// Sets the angles of the bones given by name and applies them. Unknown bones are skipped
+    #[func]
+    pub fn set_bone_angles(&mut self, angles: Dictionary) {
+        let Some(mut skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return;
+        };
+        angles.iter_shared().for_each(|(name, angle)| {
+            let idx = skeleton.find_bone(&name.stringify());
+            match (usize::try_from(idx), angle.try_to::<f32>()) {
+                (Ok(idx), Ok(angle)) if idx < self.angles.len() => self.angles[idx] = angle,
+                _ => godot_warn!("Skipping angle for `{name}`"),
+            }
+        });
+        self.apply_angles(&mut skeleton);
+    }
+
+    /// Jacobian of the last frame (column-major, see `get_jacobian_shape`)
+    #[func]
+    pub fn get_jacobian(&self) -> PackedFloat32Array {
+        PackedFloat32Array::from(self.jacobian.as_slice())
+    }
+
+    /// Rows (effector coordinates) and columns (active joints) of the Jacobian
+    #[func]
+    pub fn get_jacobian_shape(&self) -> Vector2i {
+        let (rows, cols) = self.jacobian_shape();
+        Vector2i::new(rows as i32, cols as i32)
+    }
 
-    angles: Vec<f32>,
+    /// Global poses of the tool center points in effector order (see `main_slot`)
+    #[func]
+    pub fn get_effector_poses(&self) -> Array<Transform3D> {
+        let Some(skeleton) = self.base().get_skeleton() else {
+            return Array::new();
+        };
+        self.effector_bones(&skeleton)
+            .iter()
+            .zip(self.tool_offsets())
+            .filter(|(idx, _)| **idx != -1)
+            .map(|(idx, tcp)| {
+                skeleton.get_global_transform() * skeleton.get_bone_global_pose(*idx) * tcp
+            })
+            .collect()
+    }
 
-    #[export]
-    #[var(get,set = set_main_effector)]
-    main_effector: GString,
+    /// Whether the main effector can reach the position of `target`. Runs the solver to
+    /// convergence on a scratch copy of the angles
+    #[func]
+    pub fn is_reachable(&self, target: Transform3D) -> bool {
+        self.reach(false, target.origin)
+            .is_some_and(|(_, result)| result.residual <= self.reach_tolerance)
+    }
 
-    #[export]
-    #[var(get,set = set_secondary_effector)]
-    secondary_effector: GString,
+    /// `target` moved to the closest position the main effector can reach
+    #[func]
+    pub fn closest_reachable(&self, target: Transform3D) -> Transform3D {
+        match self.reach(false, target.origin) {
+            Some((closest, _)) => Transform3D::new(target.basis, closest),
+            None => {
+                godot_error!("Could not find `{}` in skeleton", self.main_effector_name());
+                target
+            }
+        }
+    }
 
-    #[export] // Use when >0.2.4 is released
-    main_target: Option<Gd<RigidBody3D>>,
+    /// Global poses of all bones for hypothetical angles (all bones, by index). Neither the
+    /// skeleton nor the state of the solver is modified
+    #[func]
+    pub fn forward_kinematics(&self, angles: PackedFloat32Array) -> Array<Transform3D> {
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return Array::new();
+        };
+        if angles.len() != self.angles.len() {
+            godot_warn!(
+                "Got {} angles, expected {}. Missing angles are zero",
+                angles.len(),
+                self.angles.len()
+            );
+        }
+        let global = skeleton.get_global_transform();
+        self.chain(&skeleton)
+            .poses(angles.as_slice())
+            .poses
+            .iter()
+            .map(|pose| global * *pose)
+            .collect()
+    }
 
-    #[export] // Use when >0.2.4 is released
-    default_main_target: Option<Gd<RigidBody3D>>,
+    /// Samples random configurations within the joint limits and returns the reachable positions
+    /// of the main tool center point as a point cloud colored by manipulability (blue low, red high).
+    ///
+    /// The points are in skeleton coordinates. Add the mesh as a `MeshInstance3D` child of the
+    /// skeleton.
+    #[func]
+    pub fn build_reach_envelope(&self, samples: i32) -> Option<Gd<ArrayMesh>> {
+        if samples <= 0 {
+            godot_warn!("The reach envelope needs at least one sample");
+            return None;
+        }
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return None;
+        };
+        let Ok(bone) = usize::try_from(skeleton.find_bone(&self.main_effector_name())) else {
+            godot_error!("Could not find `{}` in skeleton", self.main_effector_name());
+            return None;
+        };
+
+        let chain = self.chain(&skeleton);
+        let samples = (0..samples)
+            .map(|_| {
+                let mut angles = self.angles.clone();
+                self.active_bones.iter().for_each(|idx| {
+                    angles[*idx as usize] = self.random_angle(*idx as usize);
+                });
+                let poses = chain.poses(&angles);
+                Sample {
+                    position: poses.point(bone, self.main_tcp.origin),
+                    manipulability: manipulability(
+                        &poses.point_jacobian(&self.active_bones, bone, self.main_tcp.origin),
+                        self.active_bones.len(),
+                    ),
+                }
+            })
+            .collect_vec();
 
-    #[export] // Use when >0.2.4 is released
-    secondary_target: Option<Gd<RigidBody3D>>,
+        reach_envelope_mesh(&samples)
+    }
 
-    #[export] // Use when >0.2.4 is released
-    default_secondary_target: Option<Gd<RigidBody3D>>,
+    /// Sets the pose of the main target directly (global coordinates). Takes precedence over
+    /// `main_target` until cleared
+    #[func]
+    pub fn set_main_target_transform(&mut self, transform: Transform3D) {
+        self.replace_target_transform(false, false, Some(transform));
+    }
 
-    #[export]
-    orientation: Option<Gd<BoneAttachment3D>>,
+    /// Follows `main_target` again
+    #[func]
+    pub fn clear_main_target_transform(&mut self) {
+        self.replace_target_transform(false, false, None);
+    }
 
-    // #[export]
-    // effectors: Array<Gd<Marker3D>>,
-    #[export]
-    #[var(get,set = set_method)]
-    method: Method,
+    /// Sets the pose of the default main target directly (global coordinates). Takes precedence
+    /// over `default_main_target` until cleared
+    #[func]
+    pub fn set_default_main_target_transform(&mut self, transform: Transform3D) {
+        self.replace_target_transform(false, true, Some(transform));
+    }
 
-    #[export]
-    min_dist: f32,
+    /// Follows `default_main_target` again
+    #[func]
+    pub fn clear_default_main_target_transform(&mut self) {
+        self.replace_target_transform(false, true, None);
+    }
 
-    base: Base<SkeletonModifier3D>,
-    tree: GodotTree,
-    differentiable: DifferentiableModel<f32>,
-    // indices of active bones
-    active_bones: Vec<i32>,
-}
+    /// Sets the pose of the secondary target directly (global coordinates). Takes precedence
+    /// over `secondary_target` until cleared
+    #[func]
+    pub fn set_secondary_target_transform(&mut self, transform: Transform3D) {
+        self.replace_target_transform(true, false, Some(transform));
+    }
 
-impl RsMannequinIK {
-    /// Update the mannequin-related structures
-    ///
-    /// TODO figure out where to call it for hot-reloading
-    fn update_mannequin(&mut self) {
-        godot_print!(
-            "Main: `{}`, secondary: `{}`",
-            self.main_effector,
-            self.secondary_effector
-        );
-        if let Some(skeleton) = self.base().get_skeleton() {
-            // Use BoneAttachments instead! Select them in the UI
+    /// Follows `secondary_target` again
+    #[func]
+    pub fn clear_secondary_target_transform(&mut self) {
+        self.replace_target_transform(true, false, None);
+    }
 
-            let main_effector_idx = skeleton.find_bone(&self.main_effector);
-            if main_effector_idx == -1 {
-                godot_error!("Could not find `{}` in skeleton", self.main_effector);
-                return;
-            }
+    /// Sets the pose of the default secondary target directly (global coordinates). Takes
+    /// precedence over `default_secondary_target` until cleared
+    #[func]
+    pub fn set_default_secondary_target_transform(&mut self, transform: Transform3D) {
+        self.replace_target_transform(true, true, Some(transform));
+    }
 
-            godot_print!("main effector: `{main_effector_idx}`");
+    /// Follows `default_secondary_target` again
+    #[func]
+    pub fn clear_default_secondary_target_transform(&mut self) {
+        self.replace_target_transform(true, true, None);
+    }
 
-            let mut effectors = vec![&main_effector_idx];
+    /// Sets `joint_limits` from a URDF file. Bones are matched by joint or link name, their
+    /// z-axes must be the joint axes (as in skeletons built by `RsUrdf.build_skeleton`). Returns
+    /// false if the file could not be imported
+    #[func]
+    pub fn bind_urdf(&mut self, path: GString) -> bool {
+        load_urdf(&path).is_some_and(|model| self.bind_model(&model))
+    }
 
-            let secondary_effector_idx = skeleton.find_bone(&self.secondary_effector);
-            if secondary_effector_idx != -1 {
-                godot_print!("secondary effector: `{main_effector_idx}`");
-                if matches!(self.method, Method::Secondary) {
-                    effectors.push(&secondary_effector_idx);
-                }
-            } else {
-                godot_warn!("Could not find `{}` in skeleton", self.secondary_effector);
-            };
+    /// Sets `joint_limits` from an MJCF file as `bind_urdf` does. Bones are matched by joint or
+    /// body name
+    #[func]
+    pub fn bind_mjcf(&mut self, path: GString) -> bool {
+        load_mjcf(&path).is_some_and(|model| self.bind_model(&model))
+    }
 
-            self.active_bones = (0..skeleton.get_bone_count())
-                .filter(|idx| skeleton.is_bone_enabled(*idx))
-                .collect_vec();
+    /// New skeleton built from `dh_table` (see `RsDhTable.build_skeleton`). Sets `joint_limits`
+    /// for it. The current skeleton is left alone: add the returned skeleton to the scene and
+    /// make this modifier its child. Null without a valid table
+    #[func]
+    pub fn build_from_dh_table(&mut self) -> Option<Gd<Skeleton3D>> {
+        let Some(table) = self.dh_table.clone() else {
+            godot_error!("No DH table set");
+            return None;
+        };
+        let model = table.bind().to_model();
+        match model {
+            Ok(model) => {
+                let skeleton = model.build_skeleton();
+                self.joint_limits = model.bind(&skeleton).0;
+                self.clamp_angles();
+                Some(skeleton)
+            }
+            Err(error) => {
+                godot_error!("Invalid DH table: {error}");
+                None
+            }
+        }
+    }
 
-            // Not yet supported
+    fn bind_model(&mut self, model: &KinematicModel) -> bool {
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return false;
+        };
+        let (limits, missing) = model.bind(&skeleton);
+        if !missing.is_empty() {
+            godot_warn!("No bones for joints {missing:?}");
+        }
+        self.joint_limits = limits;
+        self.clamp_angles();
+        true
+    }
 
-            // Angles must be computed for all joints!
-            self.angles = vec![0.0; skeleton.get_bone_count() as usize];
+    /// Writes the skeleton as URDF with the bones' z-axes as joint axes and `joint_limits`.
+    /// Disabled bones become fixed joints and the skeleton is turned Z-up. Returns false if the
+    /// file could not be written
+    #[func]
+    pub fn export_urdf(&self, path: GString) -> bool {
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return false;
+        };
+        let model = KinematicModel::from_skeleton(&skeleton, |idx| self.limits(idx));
+        match FileAccess::open(&path, ModeFlags::WRITE) {
+            Some(mut file) => {
+                file.store_string(&to_urdf(&model));
+                true
+            }
+            None => {
+                godot_error!("Could not write `{path}`");
+                false
+            }
+        }
+    }
 
-            godot_print!(
-                "Active bones (joints): {:?}",
-                self.active_bones
-                    .iter()
-                    .map(|idx| skeleton.get_bone_name(*idx))
-                    .collect_vec()
+    /// Sets all joint angles to zero and moves the skeleton to its rest pose
+    #[func]
+    pub fn reset_to_rest(&mut self) {
+        self.angles.iter_mut().for_each(|angle| *angle = 0.0);
+        self.stalled_frames = 0;
+        self.best_residual = f32::MAX;
+
+        if let Some(mut skeleton) = self.base().get_skeleton() {
+            self.apply_angles(&mut skeleton);
+        } else {
+            godot_error!("No skeleton found");
+        }
+    }
+
+    /// Moves the skeleton to a pose stored in `home_poses`. Returns false if there is no valid
+    /// pose of that name
+    #[func]
+    pub fn go_home(&mut self, pose_name: GString) -> bool {
+        let Some(pose) = self.home_poses.get(pose_name.clone()) else {
+            godot_warn!("No home pose `{pose_name}`");
+            return false;
+        };
+        let Ok(angles) = pose.try_to::<PackedFloat32Array>() else {
+            godot_error!("Home pose `{pose_name}` is not a PackedFloat32Array");
+            return false;
+        };
+        if angles.len() != self.angles.len() {
+            godot_error!(
+                "Home pose `{pose_name}` has {} angles, expected {}",
+                angles.len(),
+                self.angles.len()
             );
+            return false;
+        }
 
-            self.tree = skeleton.into();
+        self.angles = angles.to_vec();
+        self.stalled_frames = 0;
+        self.best_residual = f32::MAX;
 
-            if matches!(self.method, Method::Orientation) {
-                self.tree.iter_mut().for_each(|node| {
-                    node.get_mut().orientation = true;
-                });
-            }
+        if let Some(mut skeleton) = self.base().get_skeleton() {
+            self.apply_angles(&mut skeleton);
+        }
+        true
+    }
 
-            self.tree.iter_mut().for_each(|node| {
-                if *node.id() == main_effector_idx
-                    || (matches!(self.method, Method::Secondary)
-                        && *node.id() == secondary_effector_idx)
-                {
-                    node.get_mut().effector = true;
-                }
-            });
-            self.tree.iter().for_each(|node| {
-                godot_print!(
-                    "Created node (depth {}): {:?}. Trafo: {:?}",
-                    node.depth(),
-                    node.get(),
-                    node.id(),
-                )
-            });
-            self.differentiable.setup(
-                &self.tree,
-                &self.active_bones.iter().collect_vec(),
-                &effectors,
-            );
+    /// Stores the current angles as a home pose
+    #[func]
+    pub fn store_home(&mut self, pose_name: GString) {
+        self.home_poses
+            .set(pose_name, PackedFloat32Array::from(self.angles.as_slice()));
+    }
 
-            godot_print!("Setup. Jacobian shape: {:?}", self.differentiable.shape());
-        } else {
+    /// Locks a foot to the ground below it. Call when a step ends.
+    ///
+    /// Returns false if the foot is unknown or no ground was found. Note that the physics
+    /// space can only be queried safely from the physics thread (e.g., `_physics_process`).
+    #[func]
+    pub fn plant_foot(&mut self, name: GString) -> bool {
+        let Some(skeleton) = self.base().get_skeleton() else {
             godot_error!("No skeleton found");
+            return false;
+        };
+        let Some(mut space) = self
+            .base()
+            .get_world_3d()
+            .and_then(|world| world.get_direct_space_state())
+        else {
+            godot_error!("No physics space found");
+            return false;
+        };
+        let probe = self.ground_probe;
+
+        let Some(contact) = self.contacts.iter_mut().find(|c| c.name == name) else {
+            godot_warn!("`{name}` is not a foot");
+            return false;
+        };
+
+        let position =
+            skeleton.get_global_transform() * skeleton.get_bone_global_pose(contact.bone).origin;
+        // The foot bone (the ankle) keeps its height above the ground, but does not sink into it
+        contact.anchor = ground_contact(&mut space, position, probe)
+            .map(|ground| ground + Vector3::UP * (position.y - ground.y).max(0.0));
+        if contact.anchor.is_none() {
+            godot_warn!("No ground found below `{name}`");
         }
+        contact.anchor.is_some()
     }
-}
 
-#[godot_api]
-impl RsMannequinIK {
-    /// Changing the end-effectors requires recomputing the tree
+    /// Releases a foot from the ground. Call when a step begins.
     #[func]
-    pub fn set_main_effector(&mut self, value: GString) {
-        self.main_effector = value;
-        self.update_mannequin();
+    pub fn release_foot(&mut self, name: GString) {
+        if let Some(contact) = self.contacts.iter_mut().find(|c| c.name == name) {
+            contact.anchor = None;
+        } else {
+            godot_warn!("`{name}` is not a foot");
+        }
     }
 
-    /// Changing the end-effectors requires recomputing the tree
     #[func]
-    pub fn set_secondary_effector(&mut self, value: GString) {
-        self.secondary_effector = value;
-        self.update_mannequin();
+    pub fn plant_all_feet(&mut self) {
+        let names = self.contacts.iter().map(|c| c.name.clone()).collect_vec();
+        names.into_iter().for_each(|name| {
+            self.plant_foot(name);
+        });
     }
 
     #[func]
-    pub fn set_method(&mut self, value: Method) {
-        self.method = value;
-        godot_print!("Updated method");
-        self.update_mannequin();
+    pub fn release_all_feet(&mut self) {
+        self.contacts.iter_mut().for_each(|c| c.anchor = None);
+    }
+
+    /// Optimizes the pose to convergence without applying it (offline pose generation).
+    ///
+    /// Expects one global target pose per effector (in effector order, see `main_slot`) and
+    /// returns the optimal angles for all bones. Empty if the targets do not match. The positions
+    /// of the tool center points are optimized and, with `Method::Orientation`, their
+    /// orientations too. Neither the angles nor the Jacobian of the last frame (`get_jacobian`)
+    /// change.
+    #[func]
+    pub fn optimize_pose(&self, targets: Array<Transform3D>) -> PackedFloat32Array {
+        let Some(skeleton) = self.base().get_skeleton() else {
+            godot_error!("No skeleton found");
+            return PackedFloat32Array::new();
+        };
+
+        let to_skeleton = skeleton.get_global_transform().affine_inverse();
+        if !self.has_effectors(&skeleton) {
+            return PackedFloat32Array::new();
+        }
+        let bones = self.effector_bones(&skeleton);
+        if targets.len() != bones.len() {
+            godot_error!("Expected {} targets, got {}", bones.len(), targets.len());
+            return PackedFloat32Array::new();
+        }
+        let goals = targets
+            .iter_shared()
+            .map(|target| to_skeleton * target)
+            .collect_vec();
+        let points = bones
+            .into_iter()
+            .zip(self.tool_offsets())
+            .zip(&goals)
+            .map(|((bone, tcp), goal)| (bone as usize, tcp.origin, goal.origin))
+            .collect_vec();
+
+        let chain = self.chain(&skeleton);
+        let options = OptimizationOptions {
+            optimizer: self.optimizer,
+            bounds: self.bounds(),
+            ..Default::default()
+        };
+        let result = optimize(
+            |parameters| {
+                let angles = self.expand(&self.angles, parameters);
+                let positions = self.evaluate_points(&chain, &points, &angles);
+                if self.effector_rows() != 6 {
+                    return positions;
+                }
+
+                // Orientation rows (scaled axes) below the position rows
+                let (bases, rotations) = self.tool_rotations(&skeleton, &chain.poses(&angles));
+                let rotation_rows = 3 * goals.len();
+                Evaluation {
+                    residual: positions
+                        .residual
+                        .iter()
+                        .copied()
+                        .chain(bases.iter().zip(&goals).flat_map(|(basis, goal)| {
+                            rotation_vector(goal.basis * basis.inverse()).to_array()
+                        }))
+                        .collect(),
+                    jacobian: positions
+                        .jacobian
+                        .chunks(positions.rows)
+                        .zip(rotations.chunks(rotation_rows))
+                        .flat_map(|(position, rotation)| position.iter().chain(rotation).copied())
+                        .collect(),
+                    rows: positions.rows + rotation_rows,
+                    cols: positions.cols,
+                }
+            },
+            &self.parameters(&self.angles),
+            &options,
+        );
+        let angles = self.expand(&self.angles, &result.parameters);
+
+        godot_print!(
+            "Optimization finished after {} iterations (converged: {}, residual: {})",
+            result.iterations,
+            result.converged,
+            result.residual
+        );
+
+        PackedFloat32Array::from(angles.as_slice())
     }
 }
 
@@ -199,12 +1947,83 @@ impl ISkeletonModifier3D for RsMannequinIK {
             secondary_target: None,
             default_main_target: None,
             default_secondary_target: None,
-            tree: GodotTree::new(),
-            differentiable: DifferentiableModel::new(),
+            main_target_transform: None,
+            default_main_target_transform: None,
+            secondary_target_transform: None,
+            default_secondary_target_transform: None,
+            main_target_filter: None,
+            secondary_target_filter: None,
+            main_filter_state: FilterState::default(),
+            secondary_filter_state: FilterState::default(),
+            main_effector_attachment: None,
+            secondary_effector_attachment: None,
+            main_tcp: Transform3D::IDENTITY,
+            secondary_tcp: Transform3D::IDENTITY,
+            predict_motion: false,
+            latency: 0.05,          // seconds
+            prediction_window: 0.1, // seconds
+            feed_forward: 1.0,
+            main_history: MotionHistory::default(),
+            secondary_history: MotionHistory::default(),
+            layer_on_animation: false,
+            dh_table: None,
+            bone_weights: PackedFloat32Array::new(),
+            incoming: vec![],
+            recording_path: "user://ik_recording.json".into(),
+            playback: false,
+            recorder: None,
+            recording_time: 0.0,
+            player: Recording::default(),
+            playback_time: 0.0,
+            jacobian: vec![],
             method: Method::Gradient,
             active_bones: vec![],
             min_dist: 1.2, // meters
-            orientation: None,
+            joint_limits: PackedVector2Array::new(),
+            reachability_fallback: false,
+            reach_check_interval: 0.25, // seconds
+            reach_tolerance: 0.01,      // meters
+            grasp_offset: Vector3::ZERO,
+            grasp_rotation: Basis::IDENTITY,
+            balance: false,
+            bone_masses: PackedFloat32Array::new(),
+            support_polygon: PackedVector2Array::new(),
+            feet: PackedStringArray::new(),
+            plant_feet: false,
+            plant_pending: false,
+            ground_probe: 0.5, // meters
+            contacts: vec![],
+            multi_seed: false,
+            seed_count: 8,
+            stall_frames: 30,
+            seed_poses: Array::new(),
+            best_residual: f32::MAX,
+            home_poses: Dictionary::new(),
+            debug_draw: false,
+            debug_size: 0.1, // meters
+            debug_mesh: None,
+            verbosity: Verbosity::Warning,
+            log_categories: 0b1111,
+            log_interval: 1.0, // seconds
+            rate_limiter: RateLimiter::default(),
+            reached: vec![],
+            fallen_back: vec![],
+            fallbacks: vec![],
+            converged: false,
+            at_limit: vec![],
+            blend_policy: BlendPolicy::Switch,
+            blend_falloff: 0.3,    // meters
+            blend_fade_time: 0.5,  // seconds
+            blend_hysteresis: 0.1, // meters
+            main_blend: TargetBlend::default(),
+            secondary_blend: TargetBlend::default(),
+            main_reach_check: ReachCheck::default(),
+            secondary_reach_check: ReachCheck::default(),
+            last_ticks: 0,
+            stalled_frames: 0,
+            restart: None,
+            effectors_found: false,
+            optimizer: Optimizer::GaussNewton,
         }
     }
 
@@ -216,6 +2035,13 @@ impl ISkeletonModifier3D for RsMannequinIK {
         }
     }
 
+    fn physics_process(&mut self, _delta: f64) {
+        if self.plant_pending && !Engine::singleton().is_editor_hint() {
+            self.plant_pending = false;
+            self.plant_all_feet();
+        }
+    }
+
     fn enter_tree(&mut self) {
         self.update_mannequin();
     }
@@ -245,41 +2071,49 @@ impl ISkeletonModifier3D for RsMannequinIK {
     fn process_modification(&mut self) {
         let skeleton: Option<Gd<Skeleton3D>> = self.base().get_skeleton();
         if let Some(mut skeleton) = skeleton {
-            if let Some(main) = &self.main_target {
-                self.differentiable.compute(
-                    &self.tree,
-                    &self.angles,
-                    mannequin::differentiable::ComputeSelection::All,
-                );
-
-                // godot_print!("Computed diff {}", diff);
+            if self.playback {
+                self.play_frame(&mut skeleton);
+                return;
+            }
+            if let Some(main) = self.main_target_pose().filter(|_| self.effectors_found) {
+                if self.layer_on_animation {
+                    // The skeleton restores the animated poses before running the modifiers
+                    self.incoming = (0..skeleton.get_bone_count())
+                        .map(|idx| skeleton.get_bone_pose(idx))
+                        .collect();
+                }
+                let chain = self.chain(&skeleton);
+                let (effectors, jacobian) = self.tool_points(&skeleton, &chain);
+                self.jacobian = jacobian.clone();
+
+                // Goals in skeleton coordinates
+                let dt = self.frame_time();
+                let to_skeleton = skeleton.get_global_transform().affine_inverse();
+                let main = self.filter_target(false, main, dt);
+                let (main, main_motion) = self.predict_target(false, main, dt);
+                let main_goal = to_skeleton
+                    * self.goal(false, main, effectors[self.main_slot()], to_skeleton, dt);
+                let secondary = self
+                    .secondary_target_pose()
+                    .filter(|_| matches!(self.method, Method::Secondary | Method::Grasp))
+                    .map(|secondary| {
+                        let secondary = self.filter_target(true, secondary, dt);
+                        let (secondary, motion) = self.predict_target(true, secondary, dt);
+                        (
+                            to_skeleton * self.goal(true, secondary, effectors[0], to_skeleton, dt),
+                            motion,
+                        )
+                    });
+                let secondary_goal = secondary.map(|(goal, _)| goal);
 
-                let jacobian = self.differentiable.jacobian(); // 3x6
-                let (rows, cols) = self.differentiable.shape();
+                let jacobian = jacobian.as_slice();
+                let (rows, cols) = self.jacobian_shape();
 
-                // godot_print!(
-                //     "jacobian: ({:?}) {:?}",
-                //     self.differentiable.shape(),
-                //     jacobian
-                // );
-                let update = match self.method {
+                // Jacobian of the relative task of `Method::Grasp`, which has priority
+                let mut grasp_task = None;
+                let mut update = match self.method {
                     Method::Gradient => {
-                        let effector: [f32; 3] =
-                            self.differentiable.effectors()[0].try_into().unwrap();
-
-                        let mut diff = skeleton.get_global_transform().affine_inverse()
-                            * main.get_global_position()
-                            - Vector3::from_array(effector);
-
-                        if diff.length() > self.min_dist {
-                            if let Some(default_main) = &self.default_main_target {
-                                diff = skeleton.get_global_transform().affine_inverse()
-                                    * default_main.get_global_position()
-                                    - Vector3::from_array(effector)
-                            } else {
-                                godot_warn!("No default main target")
-                            }
-                        }
+                        let diff = main_goal.origin - effectors[0];
 
                         let mut update = jacobian
                             .chunks(rows)
@@ -296,21 +2130,7 @@ impl ISkeletonModifier3D for RsMannequinIK {
 
                     Method::Solve => {
                         let mut update = vec![0f32; self.active_bones.len()];
-                        let effector: [f32; 3] =
-                            self.differentiable.effectors()[0].try_into().unwrap();
-
-                        let mut diff = skeleton.get_global_transform().affine_inverse()
-                            * main.get_global_position()
-                            - Vector3::from_array(effector);
-                        if diff.length() > self.min_dist {
-                            if let Some(default_main) = &self.default_main_target {
-                                diff = skeleton.get_global_transform().affine_inverse()
-                                    * default_main.get_global_position()
-                                    - Vector3::from_array(effector)
-                            } else {
-                                godot_warn!("No default main target")
-                            }
-                        }
+                        let diff = main_goal.origin - effectors[0];
 
                         solve_linear(
                             jacobian,
@@ -325,39 +2145,9 @@ impl ISkeletonModifier3D for RsMannequinIK {
 
                     Method::Secondary => {
                         let mut update = vec![0f32; self.active_bones.len()];
-                        if let Some(secondary) = &self.secondary_target {
-                            let mut effector: [f32; 3] =
-                                self.differentiable.effectors()[1].try_into().unwrap();
-
-                            let mut diff_1 = skeleton.get_global_transform().affine_inverse()
-                                * main.get_global_position()
-                                - Vector3::from_array(effector);
-
-                            if diff_1.length() > self.min_dist {
-                                if let Some(default_main) = &self.default_main_target {
-                                    diff_1 = skeleton.get_global_transform().affine_inverse()
-                                        * default_main.get_global_position()
-                                        - Vector3::from_array(effector)
-                                } else {
-                                    godot_warn!("No default main target")
-                                }
-                            }
-
-                            effector = self.differentiable.effectors()[0].try_into().unwrap();
-
-                            let mut diff_2 = skeleton.get_global_transform().affine_inverse()
-                                * secondary.get_global_position()
-                                - Vector3::from_array(effector);
-
-                            if diff_2.length() > self.min_dist {
-                                if let Some(default) = &self.default_secondary_target {
-                                    diff_2 = skeleton.get_global_transform().affine_inverse()
-                                        * default.get_global_position()
-                                        - Vector3::from_array(effector)
-                                } else {
-                                    godot_warn!("No default scnd target")
-                                }
-                            }
+                        if let Some(secondary_goal) = secondary_goal {
+                            let diff_1 = main_goal.origin - effectors[1];
+                            let diff_2 = secondary_goal.origin - effectors[0];
 
                             solve_secondary_goals(
                                 jacobian,
@@ -369,120 +2159,263 @@ impl ISkeletonModifier3D for RsMannequinIK {
                                 PI / 180.0 * self.velocity,
                             );
                         } else {
-                            godot_warn!("No secondary target set")
+                            ik_log!(self, Targets, Warning, "No secondary target set")
                         }
                         update
                     }
                     Method::Orientation => {
                         let mut update = vec![0f32; self.active_bones.len()];
-                        if let Some(orientation) = &self.orientation {
-                            // Why are we using a bonemarker? Answer: It is just simpler to use
-                            // Godot's forward kinematics for the orientation
-
-                            let effector = skeleton.find_bone(&self.main_effector);
-
-                            // In skeleton coordinates
-                            // skeleton.get_bone_global_pose_override(bone_idx)
-                            let pose = skeleton.get_bone_global_pose_override(effector);
-                            godot_print!("Pose: {:?}", pose);
-
-                            let pose = orientation.get_transform();
-
-                            godot_print!("Orientation object pose: {:?}", pose.origin);
-
-                            // transform target into skeleton coordinates and into bone coordinates
-                            let mut relative = pose.affine_inverse()
-                                * skeleton.get_global_transform().affine_inverse()
-                                * main.get_global_transform();
-
-                            if relative.origin.length() > self.min_dist {
-                                if let Some(default_main) = &self.default_main_target {
-                                    relative = pose.affine_inverse()
-                                        * skeleton.get_global_transform().affine_inverse()
-                                        * default_main.get_global_transform();
-                                } else {
-                                    godot_warn!("No default main target");
-                                    unreachable!() //hopefully
-                                }
-                            }
-
-                            // Convert to quaternions ..
-                            let quaternions = relative.basis.get_quaternion();
-
-                            let angle = 2.0 * quaternions.w.acos();
-
-                            godot_print!("Quaternions: {:?}", quaternions);
-                            godot_print!("angle: {:?}", angle);
-
-                            let denominator = (1.0 - quaternions.w.powi(2)).sqrt();
-                            godot_print!("demnominator {denominator:>}");
-
-                            // This is a scaled axis angle representation favorable for IK
-                            let scaled_axis = if denominator > 1e-5 {
-                                Vector3::new(quaternions.x, quaternions.y, quaternions.z) * angle
-                                    / denominator
-                            } else {
-                                Vector3::new(0.0, 0.0, 0.0)
-                            };
-
-                            godot_print!("Orientation {scaled_axis:?}");
+                        // Errors in skeleton coordinates like the rows of the Jacobian
+                        let (bases, _) = self.tool_rotations(&skeleton, &chain.poses(&self.angles));
+                        let position = main_goal.origin - effectors[0];
+                        // This is a scaled axis angle representation favorable for IK
+                        let scaled_axis = rotation_vector(main_goal.basis * bases[0].inverse());
+                        ik_log!(self, Pose, Debug, "Orientation {scaled_axis:?}");
+
+                        let diff = [position.to_array(), scaled_axis.to_array()].concat();
+                        ik_log!(self, Solver, Debug, "Diff before solve {diff:?}");
+                        ik_log!(
+                            self,
+                            Solver,
+                            Debug,
+                            "Jacobian: {:?}",
+                            MatRef::from_column_major_slice(jacobian, rows, cols)
+                        );
 
-                            // .. get the 6D end effector
-                            let effector: [f32; 6] =
-                                self.differentiable.effectors()[0].try_into().unwrap();
+                        solve_linear(
+                            jacobian,
+                            rows,
+                            cols,
+                            &diff,
+                            &mut update,
+                            PI / 180.0 * self.velocity,
+                        );
+                        ik_log!(self, Solver, Debug, "update: {update:?}");
+                        update
+                    }
+                    Method::Grasp => {
+                        let mut update = vec![0f32; self.active_bones.len()];
+                        // Same effector order as in `Method::Secondary`
+                        let main_effector = effectors[1];
+                        let secondary_effector = effectors[0];
+
+                        let diff = main_goal.origin - main_effector;
+
+                        // Keep the relative position and orientation of the grasp
+                        let (bases, rotations) =
+                            self.tool_rotations(&skeleton, &chain.poses(&self.angles));
+                        let lever = bases[1] * self.grasp_offset;
+                        let position = lever - (secondary_effector - main_effector);
+                        let orientation =
+                            rotation_vector(bases[1] * self.grasp_rotation * bases[0].inverse());
+                        let relative = [position.to_array(), orientation.to_array()].concat();
+                        let task = relative_jacobian(
+                            jacobian,
+                            &rotations,
+                            lever.to_array(),
+                            rows,
+                            cols,
+                            1,
+                            0,
+                        );
 
-                            godot_print!("Effector {:?}", &effector[0..3]);
+                        solve_relative_constraint(
+                            jacobian,
+                            &task,
+                            rows,
+                            cols,
+                            1,
+                            &diff.to_array(),
+                            &relative,
+                            &mut update,
+                            PI / 180.0 * self.velocity,
+                        );
+                        grasp_task = Some(task);
+                        update
+                    }
+                };
 
-                            // Compute distance and alternatively pick
+                let poses = chain.poses(&self.angles);
+
+                // Velocity feed-forward of the live targets, fading out with their default
+                // targets. It must not disturb the task with priority: the grasp, or with
+                // `Method::Secondary` the main effector for the secondary one
+                let main_rows = (0..cols)
+                    .flat_map(|col| {
+                        let start = col * rows + self.main_offset();
+                        jacobian[start..start + 3].to_vec()
+                    })
+                    .collect_vec();
+                let mut motions = vec![(
+                    self.main_offset(),
+                    main_motion,
+                    self.main_blend.weight(),
+                    grasp_task.as_deref().map(|task| (task, 6)),
+                )];
+                if matches!(self.method, Method::Secondary) {
+                    motions.extend(secondary.map(|(_, motion)| {
+                        (
+                            0,
+                            motion,
+                            self.secondary_blend.weight(),
+                            Some((main_rows.as_slice(), 3)),
+                        )
+                    }));
+                }
+                motions
+                    .into_iter()
+                    .filter(|(_, motion, weight, _)| {
+                        motion.linear != Vector3::ZERO && *weight < 1.0
+                    })
+                    .for_each(|(row, motion, weight, priority)| {
+                        add_feed_forward(
+                            jacobian,
+                            rows,
+                            cols,
+                            row,
+                            to_skeleton.basis
+                                * motion.linear
+                                * (self.feed_forward * dt * (1.0 - weight)),
+                            priority,
+                            &mut update,
+                            PI / 180.0 * self.velocity,
+                        );
+                    });
 
-                            let diff = [
-                                relative.origin.x,
-                                relative.origin.y,
-                                relative.origin.z,
-                                scaled_axis.x,
-                                scaled_axis.y,
-                                scaled_axis.z,
-                            ];
+                if self.balance {
+                    // Balancing is subordinate to reaching the targets
+                    let masses = self.bone_masses.as_slice();
+                    let projection = ground_projection(center_of_mass(&poses, masses));
+
+                    if let Some(correction) =
+                        support_correction(self.support_polygon.as_slice(), projection)
+                    {
+                        let com_jacobian =
+                            center_of_mass_jacobian(&poses, masses, &self.active_bones);
+                        // Only the ground components (x and z)
+                        let ground_jacobian = com_jacobian
+                            .chunks(3)
+                            .flat_map(|col| [col[0], col[2]])
+                            .collect_vec();
 
-                            godot_print!("Diff before solve {diff:?}");
+                        add_null_space_task(
+                            jacobian,
+                            rows,
+                            cols,
+                            &ground_jacobian,
+                            2,
+                            &correction.to_array(),
+                            &mut update,
+                            PI / 180.0 * self.velocity,
+                        );
+                    }
+                }
 
-                            let matrix = MatRef::from_column_major_slice(jacobian, rows, cols);
-                            godot_print!("Jacobian: {matrix:?}");
+                let planted = self
+                    .contacts
+                    .iter()
+                    .filter_map(|contact| contact.anchor.map(|anchor| (contact.bone, anchor)))
+                    .collect_vec();
+                if !planted.is_empty() {
+                    // Planted feet take priority, the other tasks only move in their null space
+                    let to_skeleton = skeleton.get_global_transform().affine_inverse();
+                    let feet_jacobians = planted
+                        .iter()
+                        .map(|(bone, _)| {
+                            poses.point_jacobian(&self.active_bones, *bone as usize, Vector3::ZERO)
+                        })
+                        .collect_vec();
+                    // Stack the 3-row Jacobians of all feet
+                    let feet_jacobian = (0..self.active_bones.len())
+                        .flat_map(|col| {
+                            feet_jacobians
+                                .iter()
+                                .flat_map(move |jacobian| jacobian[3 * col..3 * col + 3].to_vec())
+                        })
+                        .collect_vec();
+                    let feet_diff = planted
+                        .iter()
+                        .flat_map(|(bone, anchor)| {
+                            (to_skeleton * *anchor - poses.point(*bone as usize, Vector3::ZERO))
+                                .to_array()
+                        })
+                        .collect_vec();
+
+                    prioritize(
+                        &feet_jacobian,
+                        3 * planted.len(),
+                        cols,
+                        &feet_diff,
+                        &mut update,
+                        PI / 180.0 * self.velocity,
+                    );
+                }
 
-                            solve_linear(
-                                jacobian,
-                                rows,
-                                cols,
-                                &diff,
-                                &mut update,
-                                PI / 180.0 * self.velocity,
-                            );
-                            godot_print!("update: {update:?}");
-                        } else {
-                            godot_error!(
-                                "You need to attach an BoneAttachment3d to the tip first and assign it to the orientation field"
-                            );
+                let angles = &mut self.angles;
+                self.active_bones
+                    .iter()
+                    .zip(&update)
+                    .for_each(|(idx, update)| {
+                        angles[*idx as usize] += *update;
+                    });
+                let limited = self.clamp_angles();
+
+                let goals = if matches!(self.method, Method::Secondary | Method::Grasp) {
+                    vec![
+                        secondary_goal.map(|goal| goal.origin),
+                        Some(main_goal.origin),
+                    ]
+                } else {
+                    vec![Some(main_goal.origin)]
+                };
+                self.record_frame(dt, &goals, &effectors);
+
+                let signals = self.lifecycle_signals(&skeleton, &effectors, &update, &limited);
+
+                if self.multi_seed {
+                    let effector = effectors[self.main_slot()];
+                    let target = skeleton.get_global_transform().affine_inverse() * main.origin;
+                    let residual = (target - effector).length();
+
+                    // Effectors without a goal of their own keep their current positions
+                    let mut goals = effectors.clone();
+                    goals[self.main_slot()] = target;
+                    match self.method {
+                        Method::Secondary => {
+                            if let Some(goal) = secondary_goal {
+                                goals[0] = goal.origin;
+                            }
+                        }
+                        Method::Grasp => {
+                            let (bases, _) =
+                                self.tool_rotations(&skeleton, &chain.poses(&self.angles));
+                            goals[0] = target + bases[1] * self.grasp_offset;
                         }
+                        _ => {}
+                    }
 
-                        update
+                    if self.restart.is_some() {
+                        self.restart_from_seeds(&skeleton, &goals);
+                    } else if residual <= self.min_dist && self.is_stalled(residual) {
+                        // Out of reach targets are handled by the default target instead
+                        self.start_restart();
+                        self.restart_from_seeds(&skeleton, &goals);
                     }
-                };
-                // godot_print!("angles: {:?} result: {result:?}", self.angles);
+                }
 
-                self.angles
-                    .iter_mut()
-                    .filter_active(self.differentiable.active())
-                    .zip(&update)
-                    .for_each(|(angle, update)| {
-                        *angle += *update;
-                    });
+                self.apply_angles(&mut skeleton);
 
-                self.angles.iter().enumerate().for_each(|(idx, angle)| {
-                    let mut pose = skeleton.get_bone_pose(idx as i32);
-                    pose = pose * Transform3D::IDENTITY.rotated(Vector3::BACK, *angle);
-                    skeleton.set_bone_pose(idx as i32, pose);
+                signals.into_iter().for_each(|(signal, arguments)| {
+                    self.base_mut().emit_signal(signal, &arguments);
                 });
             }
+
+            if self.debug_draw {
+                let lines = self.debug_lines(&skeleton);
+                lines.draw(&mut self.debug_mesh());
+            } else if let Some(mesh) = &mut self.debug_mesh {
+                mesh.clear_surfaces();
+            }
         }
     }
 }
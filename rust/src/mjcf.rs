@@ -0,0 +1,450 @@
+//! Import of robots described in MJCF (MuJoCo XML)
+//!
+//! Bodies become links and hinge joints rotate them. Joint attributes are resolved through the
+//! `<default>` classes. Geometry, actuators and `<include>` are ignored. MuJoCo is Z-up like
+//! URDF, so the model keeps the world frame and the built skeleton turns it upright.
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use godot::{classes::Skeleton3D, prelude::*};
+
+use crate::model::{JointKind, KinematicJoint, KinematicModel};
+use crate::xml::XmlElement;
+
+type Attributes = HashMap<String, String>;
+
+struct Parser {
+    // factor from the compiler's angle unit to radians
+    angle: f32,
+    // intrinsic (lowercase) or extrinsic (uppercase) axes of `euler`
+    euler_sequence: String,
+    // joint attributes by default class
+    defaults: HashMap<String, Attributes>,
+    model: KinematicModel,
+}
+
+/// Kinematic tree of a `<mujoco>` element
+pub fn parse_mjcf(mujoco: &XmlElement) -> Result<KinematicModel, String> {
+    if mujoco.name != "mujoco" {
+        return Err(format!("Expected `<mujoco>`, found `<{}>`", mujoco.name));
+    }
+    let compiler = mujoco.child("compiler");
+    let mut parser = Parser {
+        // MuJoCo defaults to degrees
+        angle: match compiler.and_then(|compiler| compiler.attribute("angle")) {
+            Some("radian") => 1.0,
+            _ => PI / 180.0,
+        },
+        euler_sequence: compiler
+            .and_then(|compiler| compiler.attribute("eulerseq"))
+            .unwrap_or("xyz")
+            .to_string(),
+        defaults: HashMap::new(),
+        model: KinematicModel {
+            name: mujoco.attribute("model").unwrap_or("mujoco").to_string(),
+            root: "world".into(),
+            joints: vec![],
+        },
+    };
+    if let Some(default) = mujoco.child("default") {
+        parser.collect_defaults(default, "main", &Attributes::new());
+    }
+
+    let world = mujoco.child("worldbody").ok_or("No `<worldbody>`")?;
+    world
+        .children_named("body")
+        .try_for_each(|body| parser.parse_body(body, None, Transform3D::IDENTITY, "main"))?;
+    Ok(parser.model)
+}
+
+impl Parser {
+    /// Joint attributes of nested default classes, inheriting from their parents
+    fn collect_defaults(&mut self, default: &XmlElement, class: &str, inherited: &Attributes) {
+        let mut attributes = inherited.clone();
+        if let Some(joint) = default.child("joint") {
+            attributes.extend(joint.attributes.clone());
+        }
+        default.children_named("default").for_each(|child| {
+            let class = child.attribute("class").unwrap_or(class);
+            self.collect_defaults(child, class, &attributes);
+        });
+        self.defaults.insert(class.to_string(), attributes);
+    }
+
+    /// Attributes of a joint merged with its default class
+    fn joint_attributes(&self, joint: &XmlElement, child_class: &str) -> Attributes {
+        let class = joint.attribute("class").unwrap_or(child_class);
+        let mut attributes = self.defaults.get(class).cloned().unwrap_or_default();
+        attributes.extend(joint.attributes.clone());
+        attributes
+    }
+
+    fn rotation(&self, element: &XmlElement) -> Basis {
+        if let Some([w, x, y, z]) = element.numbers("quat").as_deref() {
+            return Basis::from_quaternion(Quaternion::new(*x, *y, *z, *w).normalized());
+        }
+        if let Some([x, y, z, angle]) = element.numbers("axisangle").as_deref() {
+            return Basis::from_axis_angle(
+                Vector3::new(*x, *y, *z).normalized(),
+                angle * self.angle,
+            );
+        }
+        if let Some(angles) = element.numbers("euler") {
+            return self.euler_sequence.chars().zip(angles).fold(
+                Basis::IDENTITY,
+                |basis, (axis, angle)| {
+                    let rotation = Basis::from_axis_angle(
+                        match axis.to_ascii_lowercase() {
+                            'x' => Vector3::RIGHT,
+                            'y' => Vector3::UP,
+                            _ => Vector3::BACK,
+                        },
+                        angle * self.angle,
+                    );
+                    if axis.is_lowercase() {
+                        basis * rotation
+                    } else {
+                        rotation * basis
+                    }
+                },
+            );
+        }
+        if ["xyaxes", "zaxis"]
+            .iter()
+            .any(|name| element.attribute(name).is_some())
+        {
+            godot_warn!(
+                "Ignoring the orientation of `{}`",
+                element.attribute("name").unwrap_or("body")
+            );
+        }
+        Basis::IDENTITY
+    }
+
+    /// Adds the joints of a body and recurses into its children. `offset` is the pose of the
+    /// body relative to the link of `parent`
+    fn parse_body(
+        &mut self,
+        body: &XmlElement,
+        parent: Option<usize>,
+        offset: Transform3D,
+        child_class: &str,
+    ) -> Result<(), String> {
+        let name = body
+            .attribute("name")
+            .map_or(format!("body{}", self.model.joints.len()), str::to_string);
+        let child_class = body.attribute("childclass").unwrap_or(child_class);
+        let pose = offset
+            * Transform3D::new(
+                self.rotation(body),
+                body.vector("pos").unwrap_or(Vector3::ZERO),
+            );
+
+        let joints = body
+            .children_named("joint")
+            .map(|joint| self.joint_attributes(joint, child_class))
+            .collect::<Vec<_>>();
+
+        // Each joint moves a link. The last one is the body itself
+        let mut parent = parent;
+        // Pose of the next joint relative to the link of `parent`
+        let mut origin = pose;
+        // Position of the current link in the body frame
+        let mut anchor = Vector3::ZERO;
+        let mut moved = false;
+        for (idx, attributes) in joints.iter().enumerate() {
+            let joint = XmlElement {
+                name: "joint".into(),
+                attributes: attributes.clone(),
+                children: vec![],
+            };
+            let joint_name = joint
+                .attribute("name")
+                .map_or(format!("{name}_joint{idx}"), str::to_string);
+            let position = joint.vector("pos").unwrap_or(Vector3::ZERO);
+
+            let kind = match joint.attribute("type").unwrap_or("hinge") {
+                "hinge" => JointKind::Revolute,
+                "slide" => JointKind::Prismatic,
+                "free" => {
+                    // Floating base, the world does not move the robot
+                    continue;
+                }
+                kind => {
+                    godot_warn!("Joint `{joint_name}` of type `{kind}` is imported as fixed");
+                    JointKind::Fixed
+                }
+            };
+            let range = joint
+                .numbers("range")
+                .and_then(|range| match range.as_slice() {
+                    [lower, upper] if lower < upper => {
+                        Some((lower * self.angle, upper * self.angle))
+                    }
+                    _ => None,
+                });
+            let limited = joint.attribute("limited").unwrap_or("auto") != "false";
+            let kind = match (kind, range.filter(|_| limited)) {
+                (JointKind::Revolute, None) => JointKind::Continuous,
+                (kind, _) => kind,
+            };
+
+            self.model.joints.push(KinematicJoint {
+                name: joint_name,
+                link: if idx + 1 == joints.len() {
+                    name.clone()
+                } else {
+                    format!("{name}/{idx}")
+                },
+                parent,
+                origin: origin * Transform3D::new(Basis::IDENTITY, position - anchor),
+                axis: joint.vector("axis").unwrap_or(Vector3::BACK),
+                kind,
+                limits: range.filter(|_| limited && kind == JointKind::Revolute),
+            });
+            parent = Some(self.model.joints.len() - 1);
+            origin = Transform3D::IDENTITY;
+            anchor = position;
+            moved = true;
+        }
+
+        // The last link is at the anchor of the last joint, not at the body origin
+        let offset = if moved {
+            Transform3D::new(Basis::IDENTITY, -anchor)
+        } else {
+            // No joints (or only a free joint), the body is welded to its parent
+            self.model.joints.push(KinematicJoint {
+                name: format!("{name}_weld"),
+                link: name.clone(),
+                parent,
+                origin,
+                axis: Vector3::BACK,
+                kind: JointKind::Fixed,
+                limits: None,
+            });
+            parent = Some(self.model.joints.len() - 1);
+            Transform3D::IDENTITY
+        };
+
+        body.children_named("body")
+            .try_for_each(|child| self.parse_body(child, parent, offset, child_class))
+    }
+}
+
+/// Reads and parses an MJCF file, logging errors
+pub fn load_mjcf(path: &GString) -> Option<KinematicModel> {
+    match XmlElement::load(path).and_then(|mujoco| parse_mjcf(&mujoco)) {
+        Ok(model) => Some(model),
+        Err(error) => {
+            godot_error!("Could not import `{path}`: {error}");
+            None
+        }
+    }
+}
+
+/// Builds skeletons from MJCF files
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct RsMjcf {
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl RsMjcf {
+    /// Skeleton with one bone per body (and per additional joint of a body) whose z-axes are the
+    /// joint axes. The root bone rotates the Z-up world to Godot's Y-up. Assign the joint limits
+    /// with `RsMannequinIK.bind_mjcf`
+    #[func]
+    pub fn build_skeleton(path: GString) -> Option<Gd<Skeleton3D>> {
+        load_mjcf(&path).map(|model| model.build_skeleton())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, attributes: &[(&str, &str)], children: Vec<XmlElement>) -> XmlElement {
+        XmlElement {
+            name: name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            children,
+        }
+    }
+
+    fn joint(attributes: &[(&str, &str)]) -> XmlElement {
+        element("joint", attributes, vec![])
+    }
+
+    fn mujoco(children: Vec<XmlElement>) -> XmlElement {
+        element("mujoco", &[("model", "test")], children)
+    }
+
+    fn world(bodies: Vec<XmlElement>) -> XmlElement {
+        element("worldbody", &[], bodies)
+    }
+
+    fn find<'a>(model: &'a KinematicModel, name: &str) -> &'a KinematicJoint {
+        model
+            .joints
+            .iter()
+            .find(|joint| joint.name == name)
+            .unwrap_or_else(|| panic!("No joint `{name}` in {model:?}"))
+    }
+
+    fn assert_limits(joint: &KinematicJoint, expected: (f32, f32)) {
+        let (lower, upper) = joint.limits.unwrap();
+        assert!(
+            (lower - expected.0).abs() < 1e-6 && (upper - expected.1).abs() < 1e-6,
+            "{joint:?}"
+        );
+    }
+
+    #[test]
+    fn inherits_default_classes() {
+        let defaults = element(
+            "default",
+            &[],
+            vec![
+                joint(&[("range", "-90 90")]),
+                element(
+                    "default",
+                    &[("class", "arm")],
+                    vec![joint(&[("axis", "0 1 0")])],
+                ),
+            ],
+        );
+        let lower = element(
+            "body",
+            &[("name", "lower"), ("pos", "0 0 0.5")],
+            vec![
+                joint(&[("name", "elbow")]),
+                joint(&[("name", "roll"), ("class", "main"), ("range", "-45 45")]),
+            ],
+        );
+        let upper = element(
+            "body",
+            &[("name", "upper"), ("childclass", "arm")],
+            vec![joint(&[("name", "shoulder")]), lower],
+        );
+        let model = parse_mjcf(&mujoco(vec![defaults, world(vec![upper])])).unwrap();
+
+        // Class `arm` inherits the range of the main class and overrides the axis
+        let shoulder = find(&model, "shoulder");
+        assert_eq!(shoulder.axis, Vector3::UP);
+        assert_eq!(shoulder.kind, JointKind::Revolute);
+        assert_limits(shoulder, (-PI / 2.0, PI / 2.0));
+
+        // `childclass` applies to nested bodies
+        assert_eq!(find(&model, "elbow").axis, Vector3::UP);
+
+        // An explicit class and attribute take precedence, in degrees by default
+        let roll = find(&model, "roll");
+        assert_eq!(roll.axis, Vector3::BACK);
+        assert_limits(roll, (-PI / 4.0, PI / 4.0));
+    }
+
+    #[test]
+    fn chains_the_joints_of_a_body() {
+        let hand = element("body", &[("name", "hand"), ("pos", "0 0 0.5")], vec![]);
+        let wrist = element(
+            "body",
+            &[("name", "wrist"), ("pos", "0 0 1")],
+            vec![
+                joint(&[("name", "pitch"), ("pos", "0 0 0.1"), ("axis", "1 0 0")]),
+                joint(&[("name", "yaw"), ("pos", "0 0 0.2"), ("axis", "0 1 0")]),
+                hand,
+            ],
+        );
+        let model = parse_mjcf(&mujoco(vec![world(vec![wrist])])).unwrap();
+        let names = model
+            .joints
+            .iter()
+            .map(|joint| (joint.name.as_str(), joint.link.as_str(), joint.parent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("pitch", "wrist/0", None),
+                ("yaw", "wrist", Some(0)),
+                ("hand_weld", "hand", Some(1)),
+            ]
+        );
+
+        // Joints sit at their anchors, the hand stays at its position in the wrist body
+        let origins = model
+            .joints
+            .iter()
+            .map(|joint| joint.origin.origin)
+            .collect::<Vec<_>>();
+        [1.1, 0.1, 0.3]
+            .into_iter()
+            .zip(origins)
+            .for_each(|(z, origin)| {
+                assert!(
+                    origin.is_equal_approx(Vector3::new(0.0, 0.0, z)),
+                    "{origin:?}"
+                );
+            });
+        assert_eq!(model.joints[2].kind, JointKind::Fixed);
+    }
+
+    #[test]
+    fn welds_free_bodies_to_the_world() {
+        let base = element(
+            "body",
+            &[("name", "base"), ("pos", "1 2 3")],
+            vec![joint(&[("type", "free")])],
+        );
+        let model = parse_mjcf(&mujoco(vec![world(vec![base])])).unwrap();
+        assert_eq!(model.root, "world");
+        assert_eq!(model.joints.len(), 1);
+        let weld = &model.joints[0];
+        assert_eq!((weld.name.as_str(), weld.parent), ("base_weld", None));
+        assert_eq!(weld.kind, JointKind::Fixed);
+        assert_eq!(weld.origin.origin, Vector3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn reads_ranges_and_kinds() {
+        let body = element(
+            "body",
+            &[("name", "body")],
+            vec![
+                joint(&[("name", "radians"), ("range", "-1 2")]),
+                joint(&[
+                    ("name", "unlimited"),
+                    ("range", "-1 2"),
+                    ("limited", "false"),
+                ]),
+                joint(&[("name", "continuous")]),
+                joint(&[("name", "slide"), ("type", "slide"), ("range", "0 1")]),
+            ],
+        );
+        let compiler = element("compiler", &[("angle", "radian")], vec![]);
+        let model = parse_mjcf(&mujoco(vec![compiler, world(vec![body])])).unwrap();
+
+        assert_limits(find(&model, "radians"), (-1.0, 2.0));
+        [
+            ("unlimited", JointKind::Continuous),
+            ("continuous", JointKind::Continuous),
+        ]
+        .into_iter()
+        .for_each(|(name, kind)| {
+            assert_eq!(find(&model, name).kind, kind);
+            assert_eq!(find(&model, name).limits, None);
+        });
+        assert_eq!(find(&model, "slide").kind, JointKind::Prismatic);
+        assert_eq!(find(&model, "slide").limits, None);
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(parse_mjcf(&element("robot", &[], vec![])).is_err());
+        assert!(parse_mjcf(&mujoco(vec![])).is_err());
+    }
+}
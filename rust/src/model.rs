@@ -0,0 +1,273 @@
+//! Kinematic description of a robot independent of its source format
+//!
+//! Importers (e.g., [`crate::urdf`]) produce a [`KinematicModel`] from which a procedural
+//! [`Skeleton3D`] is built or whose joint limits are bound to an existing skeleton. As in
+//! [`crate::mannequin`], every joint rotates about the local z-axis (`Vector3::BACK`) of its bone,
+//! so the bones are aligned with the joint axes.
+//!
+//! Models are Z-up as in URDF. The root bone of a built skeleton turns them into Godot's Y-up.
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use godot::{classes::Skeleton3D, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JointKind {
+    Revolute,
+    /// Revolute without limits
+    Continuous,
+    /// Not supported by the solver, imported as fixed
+    Prismatic,
+    Fixed,
+}
+
+#[derive(Debug, Clone)]
+pub struct KinematicJoint {
+    pub name: String,
+    /// Link moved by the joint. Becomes the name of the bone
+    pub link: String,
+    /// Index of the parent joint. `None` for joints attached to the root link
+    pub parent: Option<usize>,
+    /// Pose of the joint relative to the frame of the parent link
+    pub origin: Transform3D,
+    /// Rotation axis in the joint frame
+    pub axis: Vector3,
+    pub kind: JointKind,
+    /// Lower and upper limit in radians
+    pub limits: Option<(f32, f32)>,
+}
+
+impl KinematicJoint {
+    /// Whether the solver moves the joint
+    pub fn is_active(&self) -> bool {
+        matches!(self.kind, JointKind::Revolute | JointKind::Continuous)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KinematicModel {
+    pub name: String,
+    /// Name of the root link. Becomes the root bone
+    pub root: String,
+    /// Parents precede their children
+    pub joints: Vec<KinematicJoint>,
+}
+
+/// Rotation turning the z-axis into `axis`
+pub fn axis_alignment(axis: Vector3) -> Basis {
+    let axis = axis.normalized();
+    let cross = Vector3::BACK.cross(axis);
+    if cross.length() < 1e-6 {
+        if axis.z > 0.0 {
+            Basis::IDENTITY
+        } else {
+            Basis::from_axis_angle(Vector3::RIGHT, PI)
+        }
+    } else {
+        Basis::from_axis_angle(cross.normalized(), Vector3::BACK.angle_to(axis))
+    }
+}
+
+/// Rotation from the Z-up convention of robot descriptions to Godot's Y-up (z becomes y)
+pub fn z_up_to_y_up() -> Basis {
+    Basis::from_axis_angle(Vector3::RIGHT, -PI / 2.0)
+}
+
+/// Rotation from fixed-axis roll, pitch and yaw angles (about x, then y, then z)
+pub fn from_rpy(rpy: Vector3) -> Basis {
+    Basis::from_axis_angle(Vector3::BACK, rpy.z)
+        * Basis::from_axis_angle(Vector3::UP, rpy.y)
+        * Basis::from_axis_angle(Vector3::RIGHT, rpy.x)
+}
+
+impl KinematicModel {
+    /// Rotation from the frame of a joint's link to its bone
+    fn alignment(&self, joint: Option<usize>) -> Basis {
+        joint.map_or(Basis::IDENTITY, |joint| {
+            axis_alignment(self.joints[joint].axis)
+        })
+    }
+
+    /// Rest pose of each joint's bone relative to its parent bone
+    pub fn rests(&self) -> Vec<Transform3D> {
+        self.joints
+            .iter()
+            .enumerate()
+            .map(|(idx, joint)| {
+                let parent = Transform3D::new(self.alignment(joint.parent), Vector3::ZERO);
+                let own = Transform3D::new(self.alignment(Some(idx)), Vector3::ZERO);
+                parent.affine_inverse() * joint.origin * own
+            })
+            .collect()
+    }
+
+    /// Skeleton with a root bone for the root link followed by one bone per joint. Joints that
+    /// the solver cannot move are disabled. The rest pose of the root bone turns the model
+    /// upright (see [`z_up_to_y_up`])
+    pub fn build_skeleton(&self) -> Gd<Skeleton3D> {
+        let mut skeleton = Skeleton3D::new_alloc();
+        skeleton.set_name(&self.name);
+
+        let root = skeleton.add_bone(&self.root);
+        skeleton.set_bone_rest(root, Transform3D::new(z_up_to_y_up(), Vector3::ZERO));
+        skeleton.set_bone_enabled(root, false);
+
+        self.joints
+            .iter()
+            .zip(self.rests())
+            .for_each(|(joint, rest)| {
+                let idx = skeleton.add_bone(&joint.link);
+                // Bone indices are joint indices shifted by the root bone
+                skeleton
+                    .set_bone_parent(idx, joint.parent.map_or(root, |parent| parent as i32 + 1));
+                skeleton.set_bone_rest(idx, rest);
+                if joint.kind == JointKind::Prismatic {
+                    godot_warn!("Prismatic joint `{}` is imported as fixed", joint.name);
+                }
+                skeleton.set_bone_enabled(idx, joint.is_active());
+            });
+        skeleton.reset_bone_poses();
+        skeleton
+    }
+
+    /// Joint limits by bone index (see `joint_limits` of `RsMannequinIK`) for the bones named
+    /// like a joint or its link. Also returns the names of the joints without a bone
+    pub fn bind(&self, skeleton: &Gd<Skeleton3D>) -> (PackedVector2Array, Vec<String>) {
+        let mut limits = PackedVector2Array::new();
+        limits.resize(skeleton.get_bone_count() as usize);
+        let mut missing = vec![];
+
+        self.joints.iter().for_each(|joint| {
+            let mut idx = skeleton.find_bone(&joint.name);
+            if idx == -1 {
+                idx = skeleton.find_bone(&joint.link);
+            }
+            match (usize::try_from(idx), joint.limits) {
+                (Ok(idx), Some((lower, upper))) => {
+                    limits.as_mut_slice()[idx] = Vector2::new(lower, upper);
+                }
+                (Ok(_), None) => {}
+                (Err(_), _) => missing.push(joint.name.clone()),
+            }
+        });
+        (limits, missing)
+    }
+}
+
+/// Fixed-axis roll, pitch and yaw angles of a rotation (inverse of [`from_rpy`])
+pub fn to_rpy(basis: Basis) -> Vector3 {
+    let r = basis.orthonormalized().rows;
+    let sin_pitch = (-r[2].x).clamp(-1.0, 1.0);
+    if sin_pitch.abs() > 1.0 - 1e-6 {
+        // Gimbal lock, only the difference of roll and yaw is defined
+        Vector3::new((sin_pitch * r[0].y).atan2(r[1].y), sin_pitch.asin(), 0.0)
+    } else {
+        Vector3::new(r[2].y.atan2(r[2].z), sin_pitch.asin(), r[1].x.atan2(r[0].x))
+    }
+}
+
+impl KinematicModel {
+    /// Model of a skeleton whose joints rotate about the bones' z-axes. Links are named after
+    /// the bones and joints after their child bones. Skeletons with several root bones get an
+    /// additional `base` link. The root link is the skeleton's frame turned Z-up, so skeletons
+    /// built by [`Self::build_skeleton`] come out as they went in
+    pub fn from_skeleton(
+        skeleton: &Gd<Skeleton3D>,
+        limits: impl Fn(usize) -> Option<(f32, f32)>,
+    ) -> Self {
+        let count = skeleton.get_bone_count();
+        let roots = (0..count)
+            .filter(|idx| skeleton.get_bone_parent(*idx) == -1)
+            .collect::<Vec<_>>();
+        let single_root = match roots.as_slice() {
+            [root] => Some(*root),
+            _ => None,
+        };
+
+        let mut model = KinematicModel {
+            name: skeleton.get_name().to_string(),
+            root: single_root.map_or("base".to_string(), |root| {
+                skeleton.get_bone_name(root).to_string()
+            }),
+            joints: vec![],
+        };
+
+        // Breadth-first such that parents precede their children. The joints of the root link
+        // are additionally moved from the skeleton's frame to the Z-up root link
+        let to_z_up = Transform3D::new(z_up_to_y_up().inverse(), Vector3::ZERO);
+        let mut queue = VecDeque::new();
+        match single_root {
+            Some(root) => queue.extend(
+                skeleton
+                    .get_bone_children(root)
+                    .as_slice()
+                    .iter()
+                    .map(|child| (*child, None, to_z_up * skeleton.get_bone_rest(root))),
+            ),
+            None => queue.extend(roots.iter().map(|root| (*root, None, to_z_up))),
+        }
+        while let Some((idx, parent, frame)) = queue.pop_front() {
+            let link = skeleton.get_bone_name(idx).to_string();
+            let kind = match (
+                skeleton.is_bone_enabled(idx) && skeleton.get_bone_parent(idx) != -1,
+                limits(idx as usize),
+            ) {
+                (false, _) => JointKind::Fixed,
+                (true, Some(_)) => JointKind::Revolute,
+                (true, None) => JointKind::Continuous,
+            };
+            model.joints.push(KinematicJoint {
+                name: format!("{link}_joint"),
+                link,
+                parent,
+                origin: frame * skeleton.get_bone_rest(idx),
+                axis: Vector3::BACK,
+                kind,
+                limits: limits(idx as usize).filter(|_| kind == JointKind::Revolute),
+            });
+            let joint = model.joints.len() - 1;
+            queue.extend(
+                skeleton
+                    .get_bone_children(idx)
+                    .as_slice()
+                    .iter()
+                    .map(|child| (*child, Some(joint), Transform3D::IDENTITY)),
+            );
+        }
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpy_round_trip() {
+        [
+            Vector3::ZERO,
+            Vector3::new(0.3, -0.2, 0.1),
+            Vector3::new(-2.5, 1.2, 3.0),
+            Vector3::new(0.0, -1.2, -0.7),
+        ]
+        .into_iter()
+        .for_each(|rpy| {
+            assert!(to_rpy(from_rpy(rpy)).is_equal_approx(rpy), "{rpy:?}");
+        });
+    }
+
+    #[test]
+    fn rpy_in_gimbal_lock() {
+        // Roll and yaw are ambiguous, the rotation is not
+        [
+            Vector3::new(0.4, PI / 2.0, -0.3),
+            Vector3::new(0.4, -PI / 2.0, 0.3),
+        ]
+        .into_iter()
+        .for_each(|rpy| {
+            let basis = from_rpy(rpy);
+            assert!(from_rpy(to_rpy(basis)).is_equal_approx(&basis), "{rpy:?}");
+        });
+    }
+}
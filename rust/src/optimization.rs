@@ -0,0 +1,313 @@
+//! Nonlinear optimization of joint angles to convergence
+//!
+//! The methods in [`crate::mannequin`] take a single, limited step per frame. The solvers in
+//! this module iterate until the residual converges instead. They are meant for offline pose
+//! generation and for validating the per-frame methods.
+
+use faer::{Col, ColMut, ColRef, Mat, MatRef, linalg::solvers::DenseSolveCore};
+use godot::prelude::*;
+
+use crate::secondary::limit;
+
+#[derive(GodotConvert, Var, Export, Debug, Clone, Copy)]
+#[godot(via = GString)]
+pub enum Optimizer {
+    GaussNewton, // first enumerator is default.
+    Bfgs,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizationOptions {
+    pub optimizer: Optimizer,
+    pub max_iterations: usize,
+    /// Converged when the norm of the residual drops below this value
+    pub tolerance: f32,
+    /// Levenberg damping of the normal equations (Gauss-Newton only)
+    pub damping: f32,
+    /// Maximal norm of a single step in radians
+    pub max_step: f32,
+    /// Lower and upper bound of each parameter (e.g., joint limits). Parameters without an entry
+    /// are not bounded. Steps are projected onto the bounds
+    pub bounds: Vec<(f32, f32)>,
+}
+
+impl Default for OptimizationOptions {
+    fn default() -> Self {
+        Self {
+            optimizer: Optimizer::GaussNewton,
+            max_iterations: 100,
+            tolerance: 1e-4,
+            damping: 1e-3,
+            max_step: 0.5,
+            bounds: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizationResult {
+    pub parameters: Vec<f32>,
+    /// Norm of the final residual
+    pub residual: f32,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Residual `target - value` and the Jacobian of `value` (column-major, `rows x cols`)
+pub struct Evaluation {
+    pub residual: Vec<f32>,
+    pub jacobian: Vec<f32>,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Evaluation {
+    fn cost(&self) -> f32 {
+        0.5 * self.residual.iter().map(|x| x.powi(2)).sum::<f32>()
+    }
+
+    pub fn residual_norm(&self) -> f32 {
+        self.residual.iter().map(|x| x.powi(2)).sum::<f32>().sqrt()
+    }
+
+    /// Negative gradient of the cost `0.5 * |r|^2`, that is, `J^T r`
+    fn descent(&self) -> Col<f32> {
+        let jacobian = MatRef::from_column_major_slice(&self.jacobian, self.rows, self.cols);
+        let residual = ColRef::from_slice(&self.residual);
+        jacobian.transpose() * residual
+    }
+}
+
+/// Clamps the parameters to their bounds
+fn project(parameters: &mut [f32], bounds: &[(f32, f32)]) {
+    parameters
+        .iter_mut()
+        .zip(bounds)
+        .for_each(|(parameter, (lower, upper))| *parameter = parameter.clamp(*lower, *upper));
+}
+
+/// Minimizes the residual returned by `evaluate`, starting at `initial` (projected onto the
+/// bounds)
+pub fn optimize(
+    mut evaluate: impl FnMut(&[f32]) -> Evaluation,
+    initial: &[f32],
+    options: &OptimizationOptions,
+) -> OptimizationResult {
+    let mut parameters = initial.to_vec();
+    project(&mut parameters, &options.bounds);
+    let mut evaluation = evaluate(&parameters);
+    let cols = parameters.len();
+
+    // Inverse Hessian approximation (BFGS only)
+    let mut hessian = Mat::<f32>::identity(cols, cols);
+    let mut fresh = true;
+
+    for iteration in 0..options.max_iterations {
+        if evaluation.residual_norm() < options.tolerance {
+            return OptimizationResult {
+                parameters,
+                residual: evaluation.residual_norm(),
+                iterations: iteration,
+                converged: true,
+            };
+        }
+
+        let descent = evaluation.descent();
+        let mut step = match options.optimizer {
+            Optimizer::GaussNewton => {
+                let jacobian = MatRef::from_column_major_slice(
+                    &evaluation.jacobian,
+                    evaluation.rows,
+                    evaluation.cols,
+                );
+                let square = jacobian.transpose() * jacobian
+                    + Mat::<f32>::identity(cols, cols) * options.damping;
+                square.partial_piv_lu().inverse() * &descent
+            }
+            Optimizer::Bfgs => &hessian * &descent,
+        };
+
+        limit(&mut step, options.max_step);
+
+        let Some((next, next_evaluation)) = line_search(
+            &mut evaluate,
+            &parameters,
+            &step,
+            &options.bounds,
+            evaluation.cost(),
+        ) else {
+            if matches!(options.optimizer, Optimizer::Bfgs) && !fresh {
+                // The curvature estimate went bad. Restart with steepest descent
+                hessian = Mat::<f32>::identity(cols, cols);
+                fresh = true;
+                continue;
+            }
+            // Stalled in a (local) minimum
+            return OptimizationResult {
+                parameters,
+                residual: evaluation.residual_norm(),
+                iterations: iteration,
+                converged: false,
+            };
+        };
+
+        if matches!(options.optimizer, Optimizer::Bfgs) {
+            let next_descent = next_evaluation.descent();
+            let s = Col::<f32>::from_fn(cols, |i| next[i] - parameters[i]);
+            // Difference of gradients (the negative of the difference of descents)
+            let y = Col::<f32>::from_fn(cols, |i| descent[i] - next_descent[i]);
+            let sy = (0..cols).map(|i| s[i] * y[i]).sum::<f32>();
+
+            if sy > 1e-8 {
+                let rho = 1.0 / sy;
+                let left = Mat::<f32>::from_fn(cols, cols, |i, k| {
+                    (if i == k { 1.0 } else { 0.0 }) - rho * s[i] * y[k]
+                });
+                let outer = Mat::<f32>::from_fn(cols, cols, |i, k| rho * s[i] * s[k]);
+                hessian = &left * &hessian * left.transpose() + outer;
+                fresh = false;
+            }
+        }
+
+        parameters = next;
+        evaluation = next_evaluation;
+    }
+
+    OptimizationResult {
+        residual: evaluation.residual_norm(),
+        converged: evaluation.residual_norm() < options.tolerance,
+        parameters,
+        iterations: options.max_iterations,
+    }
+}
+
+/// Backtracking line search along `step`, projected onto the bounds. Returns `None` if the cost
+/// cannot be decreased
+fn line_search(
+    evaluate: &mut impl FnMut(&[f32]) -> Evaluation,
+    parameters: &[f32],
+    step: &Col<f32>,
+    bounds: &[(f32, f32)],
+    cost: f32,
+) -> Option<(Vec<f32>, Evaluation)> {
+    let mut step_vec = vec![0f32; parameters.len()];
+    ColMut::from_slice_mut(&mut step_vec).copy_from(step);
+
+    let mut factor = 1.0;
+    for _ in 0..10 {
+        let mut candidate = parameters
+            .iter()
+            .zip(&step_vec)
+            .map(|(x, dx)| x + factor * dx)
+            .collect::<Vec<_>>();
+        project(&mut candidate, bounds);
+        let evaluation = evaluate(&candidate);
+        if evaluation.cost() < cost {
+            return Some((candidate, evaluation));
+        }
+        factor *= 0.5;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [f32; 2] = [1.0, 0.5];
+
+    /// Planar arm with two links reaching for `target`
+    fn planar_arm(target: [f32; 2]) -> impl FnMut(&[f32]) -> Evaluation {
+        move |angles| {
+            let (first, both) = (angles[0], angles[0] + angles[1]);
+            let tip = [
+                LENGTHS[0] * first.cos() + LENGTHS[1] * both.cos(),
+                LENGTHS[0] * first.sin() + LENGTHS[1] * both.sin(),
+            ];
+            Evaluation {
+                residual: vec![target[0] - tip[0], target[1] - tip[1]],
+                jacobian: vec![
+                    -tip[1],
+                    tip[0],
+                    -LENGTHS[1] * both.sin(),
+                    LENGTHS[1] * both.cos(),
+                ],
+                rows: 2,
+                cols: 2,
+            }
+        }
+    }
+
+    fn options(optimizer: Optimizer) -> OptimizationOptions {
+        OptimizationOptions {
+            optimizer,
+            max_iterations: 200,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn converges_to_reachable_targets() {
+        [Optimizer::GaussNewton, Optimizer::Bfgs]
+            .into_iter()
+            .for_each(|optimizer| {
+                let result = optimize(planar_arm([0.3, 1.1]), &[0.1, 0.2], &options(optimizer));
+                assert!(result.converged, "{optimizer:?}: {result:?}");
+                assert!(result.residual < 1e-4);
+                assert!(
+                    planar_arm([0.3, 1.1])(&result.parameters).residual_norm() < 1e-4,
+                    "{optimizer:?}: {result:?}"
+                );
+            });
+    }
+
+    #[test]
+    fn stretches_towards_unreachable_targets() {
+        [Optimizer::GaussNewton, Optimizer::Bfgs]
+            .into_iter()
+            .for_each(|optimizer| {
+                let result = optimize(planar_arm([3.0, 0.0]), &[0.3, 0.3], &options(optimizer));
+                assert!(!result.converged);
+                // The closest point is the stretched arm pointing at the target
+                assert!(
+                    (result.residual - 1.5).abs() < 1e-2,
+                    "{optimizer:?}: {result:?}"
+                );
+            });
+    }
+
+    #[test]
+    fn respects_bounds() {
+        let bounds = vec![(-0.2, 0.2), (0.0, 0.5)];
+        let result = optimize(
+            planar_arm([0.0, 1.5]),
+            &[1.0, -1.0],
+            &OptimizationOptions {
+                bounds: bounds.clone(),
+                ..options(Optimizer::GaussNewton)
+            },
+        );
+        assert!(
+            result
+                .parameters
+                .iter()
+                .zip(&bounds)
+                .all(|(parameter, (lower, upper))| (lower..=upper).contains(&parameter)),
+            "{result:?}"
+        );
+        // The target straight up needs the first joint at its upper bound
+        assert!((result.parameters[0] - 0.2).abs() < 1e-3, "{result:?}");
+    }
+
+    #[test]
+    fn starts_converged() {
+        let result = optimize(
+            planar_arm([1.5, 0.0]),
+            &[0.0, 0.0],
+            &options(Optimizer::Bfgs),
+        );
+        assert!(result.converged);
+        assert_eq!(result.iterations, 0);
+        assert_eq!(result.parameters, [0f32; 2]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Prediction of moving targets
+//!
+//! The solver reacts one frame late and limits its steps, so the arm lags behind moving targets
+//! (e.g., thrown objects). The velocity of a target is estimated from its recent poses and the
+//! pose is extrapolated by the latency of the pipeline. A feed-forward term additionally moves
+//! the joints with the target instead of waiting for the error to build up.
+
+use std::collections::VecDeque;
+
+use faer::{ColMut, ColRef, Mat, MatRef};
+use godot::prelude::*;
+
+use crate::secondary::{limit, pseudo_inverse_underdetermined};
+
+/// Linear (m/s) and angular (axis times rad/s) velocity
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Motion {
+    pub linear: Vector3,
+    pub angular: Vector3,
+}
+
+impl Motion {
+    /// Pose reached after moving with this velocity for `time` seconds
+    pub fn extrapolate(&self, pose: Transform3D, time: f32) -> Transform3D {
+        let angle = self.angular.length() * time;
+        let basis = if angle > 1e-6 {
+            Basis::from_axis_angle(self.angular.normalized(), angle) * pose.basis
+        } else {
+            pose.basis
+        };
+        Transform3D::new(basis, pose.origin + self.linear * time)
+    }
+}
+
+/// Adds the joint update `J⁺ d` that moves an effector by `displacement` (e.g., its target's
+/// velocity times the frame time) to `parameters`. The position Jacobian of the effector is
+/// rows `row..row + 3` of `matrix`. The update is projected into the null space of `priority`
+/// (a Jacobian with `cols` columns and the given number of rows, column-major) if there is one,
+/// such that it does not disturb a task with higher priority
+#[allow(clippy::too_many_arguments)]
+pub fn add_feed_forward(
+    matrix: &[f32],
+    rows: usize,
+    cols: usize,
+    row: usize,
+    displacement: Vector3,
+    priority: Option<(&[f32], usize)>,
+    parameters: &mut [f32],
+    limit_radians: f32,
+) {
+    let jacobian = MatRef::from_column_major_slice(matrix, rows, cols).get(row..row + 3, 0..cols);
+    let displacement = displacement.to_array();
+
+    let mut update =
+        pseudo_inverse_underdetermined(jacobian) * ColRef::from_slice(displacement.as_slice());
+    if let Some((task, task_rows)) = priority {
+        let task = MatRef::from_column_major_slice(task, task_rows, cols);
+        let projection =
+            Mat::<f32>::identity(cols, cols) - pseudo_inverse_underdetermined(task) * task;
+        update = projection * update;
+    }
+    limit(&mut update, limit_radians);
+
+    let sum = ColRef::from_slice(parameters) + update;
+    ColMut::from_slice_mut(parameters).copy_from(sum);
+}
+
+/// Recent poses of one target
+#[derive(Debug, Clone, Default)]
+pub struct MotionHistory {
+    // (time in seconds, pose), oldest first
+    samples: VecDeque<(f32, Transform3D)>,
+    time: f32,
+}
+
+impl MotionHistory {
+    /// Adds a pose taken `dt` seconds after the previous one and drops poses older than `window`
+    pub fn push(&mut self, pose: Transform3D, dt: f32, window: f32) {
+        self.time += dt;
+        self.samples.push_back((self.time, pose));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(time, _)| self.time - time > window)
+            && self.samples.len() > 2
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Average velocity over the window. Zero with less than two poses
+    pub fn motion(&self) -> Motion {
+        let (Some((start, first)), Some((end, last))) = (self.samples.front(), self.samples.back())
+        else {
+            return Motion::default();
+        };
+        let span = end - start;
+        if span <= 1e-6 {
+            return Motion::default();
+        }
+
+        let mut rotation = last.basis.get_quaternion() * first.basis.get_quaternion().inverse();
+        // shortest way
+        if rotation.w < 0.0 {
+            rotation = -rotation;
+        }
+        let angular = if rotation.w < 1.0 - 1e-7 {
+            rotation.get_axis().normalized() * rotation.get_angle() / span
+        } else {
+            Vector3::ZERO
+        };
+
+        Motion {
+            linear: (last.origin - first.origin) / span,
+            angular,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
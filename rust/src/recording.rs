@@ -0,0 +1,297 @@
+//! Recording and replaying joint trajectories
+//!
+//! A recording holds timestamped angles (all bones), the goals of the effectors and their
+//! residuals. Recordings are stored as JSON or CSV (chosen by the file extension) such that
+//! sessions on a headset can be reproduced on a desktop.
+
+use godot::{
+    classes::{FileAccess, Json, file_access::ModeFlags},
+    prelude::*,
+};
+
+/// State of one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Seconds since the start of the recording
+    pub time: f32,
+    /// Angles of all bones (by index)
+    pub angles: Vec<f32>,
+    /// Goals (skeleton coordinates) in effector order (see `RsMannequinIK::main_slot`)
+    pub targets: Vec<Option<Vector3>>,
+    /// Distance of each effector to its goal
+    pub residuals: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    /// Names of all bones (by index)
+    pub bones: Vec<String>,
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+
+    /// Angles of the last frame at or before `time`
+    pub fn angles_at(&self, time: f32) -> Option<&[f32]> {
+        let idx = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames
+            .get(idx.saturating_sub(1))
+            .map(|frame| frame.angles.as_slice())
+    }
+
+    /// Writes the recording to `path`. Files ending in `.csv` are CSV, all others JSON
+    pub fn save(&self, path: &GString) -> bool {
+        let text = if is_csv(path) {
+            GString::from(self.to_csv())
+        } else {
+            self.to_json()
+        };
+        match FileAccess::open(path, ModeFlags::WRITE) {
+            Some(mut file) => {
+                file.store_string(&text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn load(path: &GString) -> Option<Self> {
+        if !FileAccess::file_exists(path) {
+            return None;
+        }
+        let text = FileAccess::get_file_as_string(path);
+        if is_csv(path) {
+            Self::from_csv(&text.to_string())
+        } else {
+            Self::from_json(&text)
+        }
+    }
+
+    pub fn to_json(&self) -> GString {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let targets = frame
+                    .targets
+                    .iter()
+                    .map(|target| match target {
+                        Some(target) => {
+                            PackedFloat32Array::from(target.to_array().as_slice()).to_variant()
+                        }
+                        None => Variant::nil(),
+                    })
+                    .collect::<VariantArray>();
+                vdict! {
+                    "time": frame.time,
+                    "angles": PackedFloat32Array::from(frame.angles.as_slice()),
+                    "targets": targets,
+                    "residuals": PackedFloat32Array::from(frame.residuals.as_slice()),
+                }
+                .to_variant()
+            })
+            .collect::<VariantArray>();
+        let bones = self
+            .bones
+            .iter()
+            .map(GString::from)
+            .collect::<PackedStringArray>();
+        Json::stringify(&vdict! { "bones": bones, "frames": frames }.to_variant())
+    }
+
+    pub fn from_json(text: &GString) -> Option<Self> {
+        let root = Json::parse_string(text).try_to::<Dictionary>().ok()?;
+        let bones = root
+            .get("bones")?
+            .try_to::<VariantArray>()
+            .ok()?
+            .iter_shared()
+            .map(|bone| bone.stringify().to_string())
+            .collect();
+        let frames = root
+            .get("frames")?
+            .try_to::<VariantArray>()
+            .ok()?
+            .iter_shared()
+            .map(|frame| {
+                let frame = frame.try_to::<Dictionary>().ok()?;
+                let targets = frame
+                    .get("targets")?
+                    .try_to::<VariantArray>()
+                    .ok()?
+                    .iter_shared()
+                    .map(|target| {
+                        floats(&target).and_then(|values| match values.as_slice() {
+                            [x, y, z] => Some(Vector3::new(*x, *y, *z)),
+                            _ => None,
+                        })
+                    })
+                    .collect();
+                Some(Frame {
+                    time: frame.get("time")?.try_to::<f32>().ok()?,
+                    angles: floats(&frame.get("angles")?)?,
+                    targets,
+                    residuals: floats(&frame.get("residuals")?)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { bones, frames })
+    }
+
+    /// Header `time,angle:<bone>...,target<i>_x,target<i>_y,target<i>_z...,residual<i>...`.
+    /// Missing targets are empty fields
+    pub fn to_csv(&self) -> String {
+        let effectors = self.frames.first().map_or(0, |frame| frame.targets.len());
+        let mut header = vec!["time".to_string()];
+        header.extend(self.bones.iter().map(|bone| format!("angle:{bone}")));
+        header.extend(
+            (0..effectors)
+                .flat_map(|i| ["x", "y", "z"].map(|coordinate| format!("target{i}_{coordinate}"))),
+        );
+        header.extend((0..effectors).map(|i| format!("residual{i}")));
+
+        let mut lines = vec![header.join(",")];
+        lines.extend(self.frames.iter().map(|frame| {
+            let mut fields = vec![frame.time.to_string()];
+            fields.extend(frame.angles.iter().map(f32::to_string));
+            fields.extend(frame.targets.iter().flat_map(|target| match target {
+                Some(target) => target.to_array().map(|value| value.to_string()),
+                None => [String::new(), String::new(), String::new()],
+            }));
+            fields.extend(frame.residuals.iter().map(f32::to_string));
+            fields.join(",")
+        }));
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_csv(text: &str) -> Option<Self> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines.next()?.split(',').collect::<Vec<_>>();
+        let bones = header
+            .iter()
+            .filter_map(|column| column.strip_prefix("angle:"))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let effectors = header
+            .iter()
+            .filter(|column| column.starts_with("residual"))
+            .count();
+        if header.len() != 1 + bones.len() + 4 * effectors {
+            return None;
+        }
+
+        let frames = lines
+            .map(|line| {
+                let fields = line
+                    .split(',')
+                    .map(|field| field.trim().parse::<f32>().ok())
+                    .collect::<Vec<_>>();
+                if fields.len() != header.len() {
+                    return None;
+                }
+                let (time, rest) = fields.split_first()?;
+                let (angles, rest) = rest.split_at(bones.len());
+                let (targets, residuals) = rest.split_at(3 * effectors);
+                Some(Frame {
+                    time: (*time)?,
+                    angles: angles.iter().copied().collect::<Option<_>>()?,
+                    targets: targets
+                        .chunks(3)
+                        .map(|target| Some(Vector3::new(target[0]?, target[1]?, target[2]?)))
+                        .collect(),
+                    residuals: residuals.iter().copied().collect::<Option<_>>()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { bones, frames })
+    }
+}
+
+fn is_csv(path: &GString) -> bool {
+    path.to_string().to_lowercase().ends_with(".csv")
+}
+
+/// Numbers of a JSON array
+fn floats(value: &Variant) -> Option<Vec<f32>> {
+    value
+        .try_to::<VariantArray>()
+        .ok()?
+        .iter_shared()
+        .map(|value| value.try_to::<f32>().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording {
+        Recording {
+            bones: vec!["base".to_string(), "arm".to_string()],
+            frames: vec![
+                Frame {
+                    time: 0.0,
+                    angles: vec![0.0, 0.5],
+                    targets: vec![Some(Vector3::new(1.0, 2.0, 3.0)), None],
+                    residuals: vec![0.25, 0.0],
+                },
+                Frame {
+                    time: 0.1,
+                    angles: vec![-0.125, 1.5],
+                    targets: vec![Some(Vector3::new(-1.0, 0.5, 0.0)), None],
+                    residuals: vec![0.125, 0.0],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let recording = recording();
+        assert_eq!(Recording::from_csv(&recording.to_csv()), Some(recording));
+    }
+
+    #[test]
+    fn csv_header() {
+        let csv = recording().to_csv();
+        assert_eq!(
+            csv.lines().next(),
+            Some(
+                "time,angle:base,angle:arm,target0_x,target0_y,target0_z,\
+                 target1_x,target1_y,target1_z,residual0,residual1"
+            )
+        );
+    }
+
+    #[test]
+    fn csv_rejects_inconsistent_rows() {
+        let header = "time,angle:base,target0_x,target0_y,target0_z,residual0\n";
+        assert!(Recording::from_csv(&format!("{header}0,1,,,,0\n")).is_some());
+        assert_eq!(Recording::from_csv(&format!("{header}0,1,,,\n")), None);
+        assert_eq!(Recording::from_csv(&format!("{header}0,x,,,,0\n")), None);
+        assert_eq!(
+            Recording::from_csv("time,angle:base,residual0\n0,1,0\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn angles_at() {
+        let recording = recording();
+        assert_eq!(recording.angles_at(0.05), Some([0.0, 0.5].as_slice()));
+        assert_eq!(recording.angles_at(1.0), Some([-0.125, 1.5].as_slice()));
+        assert_eq!(recording.duration(), 0.1);
+    }
+
+    #[test]
+    fn csv_round_trip_without_frames() {
+        let recording = Recording {
+            bones: recording().bones,
+            frames: vec![],
+        };
+        assert_eq!(Recording::from_csv(&recording.to_csv()), Some(recording));
+    }
+}
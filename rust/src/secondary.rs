@@ -18,8 +18,6 @@ pub fn limit(col: &mut Col<f32>, factor: f32) {
         let limited = factor.min(norm);
 
         *col *= Scale(limited);
-
-        dbg!(limited, col.norm_l2());
     }
 }
 
@@ -57,3 +55,128 @@ pub fn solve_secondary_goals(
     let mut result = ColMut::from_slice_mut(parameters);
     result.copy_from(update);
 }
+
+/// Jacobian (`6 x cols`, column-major) of the transform of a second effector relative to the
+/// main effector: the differences of the two effector blocks of the position Jacobian `matrix`
+/// and of the angular Jacobian `rotations` (same layout). The offset of the secondary from the
+/// main effector, `lever` (fixed in the frame of the main effector), turns with the main
+/// effector
+pub fn relative_jacobian(
+    matrix: &[f32],
+    rotations: &[f32],
+    lever: [f32; 3],
+    rows: usize,
+    cols: usize,
+    main_block: usize,
+    secondary_block: usize,
+) -> Vec<f32> {
+    let jacobians = MatRef::from_column_major_slice(matrix, rows, cols);
+    let rotations = MatRef::from_column_major_slice(rotations, rows, cols);
+
+    (0..cols)
+        .flat_map(|col| {
+            (0..6).map(move |row| {
+                if row < 3 {
+                    // The lever moves by the angular velocity of the main effector times the lever
+                    let angular = |axis: usize| rotations[(3 * main_block + axis, col)];
+                    let (a, b) = ((row + 1) % 3, (row + 2) % 3);
+                    let turn = angular(a) * lever[b] - angular(b) * lever[a];
+                    jacobians[(3 * secondary_block + row, col)]
+                        - jacobians[(3 * main_block + row, col)]
+                        - turn
+                } else {
+                    rotations[(3 * secondary_block + row - 3, col)]
+                        - rotations[(3 * main_block + row - 3, col)]
+                }
+            })
+        })
+        .collect()
+}
+
+/// Moves the main effector while keeping the transform of a second effector relative to it
+/// fixed (closed kinematic loop, e.g., two hands on one handle)
+///
+/// The relative task (see [`relative_jacobian`]) has priority. `vector_relative` is the position
+/// error followed by the orientation error (scaled axis). The main target is pursued in the null
+/// space of the relative task.
+#[allow(clippy::too_many_arguments)]
+pub fn solve_relative_constraint(
+    matrix: &[f32],
+    relative: &[f32],
+    rows: usize,
+    cols: usize,
+    main_block: usize,
+    vector_main: &[f32],
+    vector_relative: &[f32],
+    parameters: &mut [f32],
+    limit_radians: f32,
+) {
+    let jacobians = MatRef::from_column_major_slice(matrix, rows, cols);
+    let vector_main = ColRef::from_slice(vector_main);
+    let vector_relative = ColRef::from_slice(vector_relative);
+
+    let jacobian_main = jacobians.get(3 * main_block..3 * main_block + 3, 0..cols);
+    let jacobian_relative = MatRef::from_column_major_slice(relative, 6, cols);
+
+    let pseudo_inverse_relative = pseudo_inverse_underdetermined(jacobian_relative);
+
+    let mut update = &pseudo_inverse_relative * vector_relative;
+
+    let projection =
+        Mat::<f32>::identity(cols, cols) - (&pseudo_inverse_relative * jacobian_relative);
+
+    update += projection * pseudo_inverse_underdetermined(jacobian_main) * vector_main;
+
+    limit(&mut update, limit_radians);
+    let mut result = ColMut::from_slice_mut(parameters);
+    result.copy_from(update);
+}
+
+/// Gives a task priority over `parameters` (the update of all other tasks): the result solves
+/// the task and keeps only the part of `parameters` in its null space
+pub fn prioritize(
+    task: &[f32],
+    task_rows: usize,
+    cols: usize,
+    vector: &[f32],
+    parameters: &mut [f32],
+    limit_radians: f32,
+) {
+    let task = MatRef::from_column_major_slice(task, task_rows, cols);
+    let vector = ColRef::from_slice(vector);
+
+    let pseudo_inverse = pseudo_inverse_underdetermined(task);
+    let mut update = &pseudo_inverse * vector;
+    limit(&mut update, limit_radians);
+
+    let projection = Mat::<f32>::identity(cols, cols) - (&pseudo_inverse * task);
+    let result = update + projection * ColRef::from_slice(parameters);
+    ColMut::from_slice_mut(parameters).copy_from(result);
+}
+
+/// Adds the update of a task to `parameters`, projected into the null space of a primary task
+/// such that the latter is not disturbed
+#[allow(clippy::too_many_arguments)]
+pub fn add_null_space_task(
+    primary: &[f32],
+    rows: usize,
+    cols: usize,
+    task: &[f32],
+    task_rows: usize,
+    vector: &[f32],
+    parameters: &mut [f32],
+    limit_radians: f32,
+) {
+    let primary = MatRef::from_column_major_slice(primary, rows, cols);
+    let task = MatRef::from_column_major_slice(task, task_rows, cols);
+    let vector = ColRef::from_slice(vector);
+
+    let projection =
+        Mat::<f32>::identity(cols, cols) - (pseudo_inverse_underdetermined(primary) * primary);
+
+    let mut update = projection * pseudo_inverse_underdetermined(task) * vector;
+    limit(&mut update, limit_radians);
+
+    let sum = ColRef::from_slice(parameters) + update;
+    ColMut::from_slice_mut(parameters).copy_from(sum);
+}
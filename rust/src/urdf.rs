@@ -0,0 +1,353 @@
+//! Import of robots described in URDF (Unified Robot Description Format)
+//!
+//! Links become bones and joints rotate them. Visuals, collisions and inertias are ignored.
+
+use std::collections::VecDeque;
+
+use godot::{classes::Skeleton3D, prelude::*};
+
+use crate::model::{JointKind, KinematicJoint, KinematicModel, from_rpy, to_rpy};
+use crate::xml::XmlElement;
+
+/// Kinematic tree of a `<robot>` element
+pub fn parse_urdf(robot: &XmlElement) -> Result<KinematicModel, String> {
+    if robot.name != "robot" {
+        return Err(format!("Expected `<robot>`, found `<{}>`", robot.name));
+    }
+
+    struct Joint<'a> {
+        element: &'a XmlElement,
+        parent: &'a str,
+        child: &'a str,
+    }
+    let joints = robot
+        .children_named("joint")
+        .map(|element| {
+            let link = |tag| {
+                element
+                    .child(tag)
+                    .and_then(|link| link.attribute("link"))
+                    .ok_or_else(|| format!("Joint without `<{tag}>`"))
+            };
+            Ok(Joint {
+                element,
+                parent: link("parent")?,
+                child: link("child")?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let root = robot
+        .children_named("link")
+        .filter_map(|link| link.attribute("name"))
+        .find(|name| joints.iter().all(|joint| joint.child != *name))
+        .ok_or("No root link")?;
+
+    // Breadth-first such that parents precede their children
+    let mut model = KinematicModel {
+        name: robot.attribute("name").unwrap_or("robot").to_string(),
+        root: root.to_string(),
+        joints: vec![],
+    };
+    let mut queue = VecDeque::from([(root, None)]);
+    while let Some((link, parent)) = queue.pop_front() {
+        for joint in joints.iter().filter(|joint| joint.parent == link) {
+            model
+                .joints
+                .push(parse_joint(joint.element, joint.child, parent)?);
+            queue.push_back((joint.child, Some(model.joints.len() - 1)));
+        }
+    }
+    if model.joints.len() != joints.len() {
+        return Err("Joints not connected to the root link (or cycles)".into());
+    }
+    Ok(model)
+}
+
+fn parse_joint(
+    element: &XmlElement,
+    link: &str,
+    parent: Option<usize>,
+) -> Result<KinematicJoint, String> {
+    let name = element.attribute("name").unwrap_or(link).to_string();
+    let kind = match element.attribute("type") {
+        Some("revolute") => JointKind::Revolute,
+        Some("continuous") => JointKind::Continuous,
+        Some("prismatic") => JointKind::Prismatic,
+        Some("fixed") => JointKind::Fixed,
+        Some(kind) => {
+            godot_warn!("Joint `{name}` of type `{kind}` is imported as fixed");
+            JointKind::Fixed
+        }
+        None => return Err(format!("Joint `{name}` without type")),
+    };
+
+    let origin = element
+        .child("origin")
+        .map_or(Transform3D::IDENTITY, |origin| {
+            Transform3D::new(
+                from_rpy(origin.vector("rpy").unwrap_or(Vector3::ZERO)),
+                origin.vector("xyz").unwrap_or(Vector3::ZERO),
+            )
+        });
+    let axis = element
+        .child("axis")
+        .and_then(|axis| axis.vector("xyz"))
+        .unwrap_or(Vector3::RIGHT);
+    if axis.length() < 1e-6 {
+        return Err(format!("Joint `{name}` with zero axis"));
+    }
+
+    let limits = match kind {
+        JointKind::Revolute => element
+            .child("limit")
+            .and_then(|limit| Some((limit.number("lower")?, limit.number("upper")?))),
+        _ => None,
+    };
+
+    Ok(KinematicJoint {
+        name,
+        link: link.to_string(),
+        parent,
+        origin,
+        axis,
+        kind,
+        limits,
+    })
+}
+
+/// Reads and parses a URDF file, logging errors
+pub fn load_urdf(path: &GString) -> Option<KinematicModel> {
+    match XmlElement::load(path).and_then(|robot| parse_urdf(&robot)) {
+        Ok(model) => Some(model),
+        Err(error) => {
+            godot_error!("Could not import `{path}`: {error}");
+            None
+        }
+    }
+}
+
+/// Builds skeletons from URDF files
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct RsUrdf {
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl RsUrdf {
+    /// Skeleton with one bone per link whose z-axes are the joint axes. The root bone rotates
+    /// the Z-up robot to Godot's Y-up. Assign the joint limits with `RsMannequinIK.bind_urdf`
+    #[func]
+    pub fn build_skeleton(path: GString) -> Option<Gd<Skeleton3D>> {
+        load_urdf(&path).map(|model| model.build_skeleton())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn numbers(values: [f32; 3]) -> String {
+    values.map(|value| value.to_string()).join(" ")
+}
+
+/// URDF document of a model. The joint frames become the link frames, so the exported joint
+/// axes are those of the model
+pub fn to_urdf(model: &KinematicModel) -> String {
+    let mut lines = vec![
+        r#"<?xml version="1.0"?>"#.to_string(),
+        format!(r#"<robot name="{}">"#, escape(&model.name)),
+        format!(r#"  <link name="{}"/>"#, escape(&model.root)),
+    ];
+    let links = |parent: Option<usize>| {
+        parent.map_or(model.root.as_str(), |parent| {
+            model.joints[parent].link.as_str()
+        })
+    };
+    model.joints.iter().for_each(|joint| {
+        let kind = match joint.kind {
+            JointKind::Revolute => "revolute",
+            JointKind::Continuous => "continuous",
+            JointKind::Prismatic => "prismatic",
+            JointKind::Fixed => "fixed",
+        };
+        lines.push(format!(r#"  <link name="{}"/>"#, escape(&joint.link)));
+        lines.push(format!(
+            r#"  <joint name="{}" type="{kind}">"#,
+            escape(&joint.name)
+        ));
+        lines.push(format!(
+            r#"    <parent link="{}"/>"#,
+            escape(links(joint.parent))
+        ));
+        lines.push(format!(r#"    <child link="{}"/>"#, escape(&joint.link)));
+        lines.push(format!(
+            r#"    <origin xyz="{}" rpy="{}"/>"#,
+            numbers(joint.origin.origin.to_array()),
+            numbers(to_rpy(joint.origin.basis).to_array())
+        ));
+        if joint.kind != JointKind::Fixed {
+            lines.push(format!(
+                r#"    <axis xyz="{}"/>"#,
+                numbers(joint.axis.normalized().to_array())
+            ));
+        }
+        if let Some((lower, upper)) = joint.limits {
+            // Effort and velocity are required by URDF but not modelled
+            lines.push(format!(
+                r#"    <limit lower="{lower}" upper="{upper}" effort="1000" velocity="10"/>"#
+            ));
+        }
+        lines.push("  </joint>".to_string());
+    });
+    lines.push("</robot>".to_string());
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, attributes: &[(&str, &str)], children: Vec<XmlElement>) -> XmlElement {
+        XmlElement {
+            name: name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            children,
+        }
+    }
+
+    fn link(name: &str) -> XmlElement {
+        element("link", &[("name", name)], vec![])
+    }
+
+    fn joint(
+        name: &str,
+        kind: &str,
+        parent: &str,
+        child: &str,
+        more: Vec<XmlElement>,
+    ) -> XmlElement {
+        let mut children = vec![
+            element("parent", &[("link", parent)], vec![]),
+            element("child", &[("link", child)], vec![]),
+        ];
+        children.extend(more);
+        element("joint", &[("name", name), ("type", kind)], children)
+    }
+
+    fn arm() -> XmlElement {
+        element(
+            "robot",
+            &[("name", "arm")],
+            vec![
+                link("lower"),
+                // Listed before its parent joint
+                joint("elbow", "continuous", "upper", "lower", vec![]),
+                link("base"),
+                link("upper"),
+                joint(
+                    "shoulder",
+                    "revolute",
+                    "base",
+                    "upper",
+                    vec![
+                        element("origin", &[("xyz", "0 0 0.5"), ("rpy", "0 0 1.5")], vec![]),
+                        element("axis", &[("xyz", "0 1 0")], vec![]),
+                        element("limit", &[("lower", "-1"), ("upper", "2")], vec![]),
+                    ],
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn parses_the_kinematic_tree() {
+        let model = parse_urdf(&arm()).unwrap();
+        assert_eq!(model.name, "arm");
+        assert_eq!(model.root, "base");
+
+        let names = model
+            .joints
+            .iter()
+            .map(|joint| joint.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["shoulder", "elbow"]);
+
+        let shoulder = &model.joints[0];
+        assert_eq!(shoulder.link, "upper");
+        assert_eq!(shoulder.parent, None);
+        assert_eq!(shoulder.kind, JointKind::Revolute);
+        assert_eq!(shoulder.axis, Vector3::UP);
+        assert_eq!(shoulder.limits, Some((-1.0, 2.0)));
+        assert_eq!(shoulder.origin.origin, Vector3::new(0.0, 0.0, 0.5));
+        assert!(
+            (shoulder.origin.basis * Vector3::RIGHT).is_equal_approx(Vector3::new(
+                1.5f32.cos(),
+                1.5f32.sin(),
+                0.0
+            ))
+        );
+
+        let elbow = &model.joints[1];
+        assert_eq!(elbow.link, "lower");
+        assert_eq!(elbow.parent, Some(0));
+        assert_eq!(elbow.kind, JointKind::Continuous);
+        // Defaults of URDF
+        assert_eq!(elbow.axis, Vector3::RIGHT);
+        assert_eq!(elbow.origin, Transform3D::IDENTITY);
+        assert_eq!(elbow.limits, None);
+    }
+
+    #[test]
+    fn rejects_invalid_robots() {
+        assert!(parse_urdf(&element("model", &[], vec![])).is_err());
+
+        let cycle = element(
+            "robot",
+            &[],
+            vec![
+                link("a"),
+                link("b"),
+                joint("ab", "fixed", "a", "b", vec![]),
+                joint("ba", "fixed", "b", "a", vec![]),
+            ],
+        );
+        assert_eq!(parse_urdf(&cycle).unwrap_err(), "No root link");
+
+        let detached = element(
+            "robot",
+            &[],
+            vec![
+                link("base"),
+                link("a"),
+                link("b"),
+                joint("ab", "fixed", "a", "b", vec![]),
+            ],
+        );
+        assert!(parse_urdf(&detached).is_err());
+
+        let zero_axis = element(
+            "robot",
+            &[],
+            vec![
+                link("a"),
+                link("b"),
+                joint(
+                    "ab",
+                    "revolute",
+                    "a",
+                    "b",
+                    vec![element("axis", &[("xyz", "0 0 0")], vec![])],
+                ),
+            ],
+        );
+        assert!(parse_urdf(&zero_axis).is_err());
+    }
+}
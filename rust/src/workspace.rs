@@ -0,0 +1,71 @@
+//! Sampling of the workspace of an effector and its visualization as a point cloud colored by
+//! manipulability
+
+use faer::MatRef;
+use godot::{
+    classes::{
+        ArrayMesh, StandardMaterial3D,
+        base_material_3d::{Flags, ShadingMode},
+        mesh::{ArrayType, PrimitiveType},
+    },
+    prelude::*,
+};
+
+/// A reachable position of the effector (skeleton coordinates)
+pub struct Sample {
+    pub position: Vector3,
+    pub manipulability: f32,
+}
+
+/// Yoshikawa's manipulability `sqrt(det(J J^T))` of a position Jacobian (`3 x cols`,
+/// column-major)
+pub fn manipulability(jacobian: &[f32], cols: usize) -> f32 {
+    let jacobian = MatRef::from_column_major_slice(jacobian, 3, cols);
+    let m = jacobian * jacobian.transpose();
+
+    let determinant = m[(0, 0)] * (m[(1, 1)] * m[(2, 2)] - m[(1, 2)] * m[(2, 1)])
+        - m[(0, 1)] * (m[(1, 0)] * m[(2, 2)] - m[(1, 2)] * m[(2, 0)])
+        + m[(0, 2)] * (m[(1, 0)] * m[(2, 1)] - m[(1, 1)] * m[(2, 0)]);
+
+    determinant.max(0.0).sqrt()
+}
+
+/// Point cloud of the samples. Blue marks low, red high manipulability. `None` without samples
+pub fn reach_envelope_mesh(samples: &[Sample]) -> Option<Gd<ArrayMesh>> {
+    if samples.is_empty() {
+        return None;
+    }
+    let maximum = samples
+        .iter()
+        .map(|sample| sample.manipulability)
+        .fold(0.0, f32::max)
+        .max(1e-8);
+
+    let vertices = samples
+        .iter()
+        .map(|sample| sample.position)
+        .collect::<PackedVector3Array>();
+    let colors = samples
+        .iter()
+        .map(|sample| {
+            let t = sample.manipulability / maximum;
+            Color::from_rgb(t, 0.2, 1.0 - t)
+        })
+        .collect::<PackedColorArray>();
+
+    let mut arrays = VariantArray::new();
+    arrays.resize(ArrayType::MAX.ord() as usize, &Variant::nil());
+    arrays.set(ArrayType::VERTEX.ord() as usize, &vertices.to_variant());
+    arrays.set(ArrayType::COLOR.ord() as usize, &colors.to_variant());
+
+    let mut material = StandardMaterial3D::new_gd();
+    material.set_shading_mode(ShadingMode::UNSHADED);
+    material.set_flag(Flags::ALBEDO_FROM_VERTEX_COLOR, true);
+    material.set_flag(Flags::USE_POINT_SIZE, true);
+    material.set_point_size(4.0);
+
+    let mut mesh = ArrayMesh::new_gd();
+    mesh.add_surface_from_arrays(PrimitiveType::POINTS, &arrays);
+    mesh.surface_set_material(0, &material);
+    Some(mesh)
+}
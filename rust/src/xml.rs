@@ -0,0 +1,105 @@
+//! Minimal XML document tree on top of Godot's [`XmlParser`]
+//!
+//! Robot descriptions (URDF, MJCF) are small, so the whole document is read into memory.
+
+use std::collections::HashMap;
+
+use godot::{
+    classes::{XmlParser, xml_parser::NodeType},
+    global::Error,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: HashMap<String, String>,
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// Reads the file at `path` (e.g., `res://robot.urdf`). Returns the root element
+    pub fn load(path: &GString) -> Result<Self, String> {
+        let mut parser = XmlParser::new_gd();
+        let error = parser.open(path);
+        if error != Error::OK {
+            return Err(format!("Could not open `{path}`: {error:?}"));
+        }
+
+        // Elements that are not closed yet
+        let mut open: Vec<XmlElement> = vec![XmlElement::default()];
+        while parser.read() == Error::OK {
+            match parser.get_node_type() {
+                NodeType::ELEMENT => {
+                    let element = XmlElement {
+                        name: parser.get_node_name().to_string(),
+                        attributes: (0..parser.get_attribute_count())
+                            .map(|idx| {
+                                (
+                                    parser.get_attribute_name(idx).to_string(),
+                                    parser.get_attribute_value(idx).to_string(),
+                                )
+                            })
+                            .collect(),
+                        children: vec![],
+                    };
+                    if parser.is_empty() {
+                        open.last_mut().unwrap().children.push(element);
+                    } else {
+                        open.push(element);
+                    }
+                }
+                NodeType::ELEMENT_END => {
+                    if open.len() < 2 {
+                        return Err(format!("Unbalanced `</{}>`", parser.get_node_name()));
+                    }
+                    let element = open.pop().unwrap();
+                    open.last_mut().unwrap().children.push(element);
+                }
+                _ => {}
+            }
+        }
+        if open.len() != 1 {
+            return Err(format!("Unclosed `<{}>`", open.last().unwrap().name));
+        }
+        open.pop()
+            .unwrap()
+            .children
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("`{path}` is empty"))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// First child with the given tag
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// All children with the given tag
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Whitespace separated numbers of an attribute (e.g., `xyz="0 0 1"`)
+    pub fn numbers(&self, name: &str) -> Option<Vec<f32>> {
+        self.attribute(name)?
+            .split_whitespace()
+            .map(|value| value.parse().ok())
+            .collect()
+    }
+
+    pub fn number(&self, name: &str) -> Option<f32> {
+        self.attribute(name)?.trim().parse().ok()
+    }
+
+    pub fn vector(&self, name: &str) -> Option<Vector3> {
+        match self.numbers(name)?.as_slice() {
+            [x, y, z] => Some(Vector3::new(*x, *y, *z)),
+            _ => None,
+        }
+    }
+}